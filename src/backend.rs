@@ -0,0 +1,593 @@
+// ==============================================================================
+// OUTPUT BACKENDS
+// ==============================================================================
+//
+// everything that touches pipewire goes through OutputBackend, so the main
+// loop doesn't care whether params end up in pw-cli, a native connection,
+// a log file or (in tests) a recording mock.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::time::{Duration, Instant};
+
+use crate::pwdump;
+
+// what discovery found out about the filter-chain sink node
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeInfo {
+    pub id: String,
+    pub name: String,
+    pub media_class: Option<String>,
+    pub playback: Option<(String, String)>, // (id, node.name) of the output side
+    pub controls: Vec<String>,              // "node:control" names, may be empty
+}
+
+impl NodeInfo {
+    pub fn new(id: &str, name: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            media_class: None,
+            playback: None,
+            controls: Vec::new(),
+        }
+    }
+
+    // whether the graph has this control; unknown controls count as supported
+    pub fn supports(&self, control: &str) -> bool {
+        self.controls.is_empty() || self.controls.iter().any(|c| c == control)
+    }
+}
+
+// what became of a push
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pushed {
+    Done(f64), // applied, took this many ms
+    Queued,    // not acknowledged yet, a later poll reports how it went
+}
+
+// filter-chain puts the capture and playback side in the same link group:
+// (id, node.name) of the other node in the group of node `id`, out of
+// (id, node.name, node.link-group) for every node
+pub fn link_group_peer<'a>(
+    id: &str,
+    link_group: Option<&str>,
+    nodes: impl IntoIterator<Item = (String, &'a str, Option<&'a str>)>,
+) -> Option<(String, String)> {
+    let group = link_group?;
+    nodes
+        .into_iter()
+        .find(|(other, _, other_group)| other != id && *other_group == Some(group))
+        .map(|(other, name, _)| (other, name.to_string()))
+}
+
+pub trait OutputBackend {
+    // short name for the connection panel
+    fn name(&self) -> &'static str;
+
+    // resolve an exact node.name to the node's id and related info
+    fn find_node(&mut self, node_name: &str) -> Option<NodeInfo>;
+
+    // the same lookup as a plain function the main loop can run on a thread
+    // of its own, for backends where it's too slow to call between packets.
+    // None: find_node is cheap, call that
+    fn background_lookup(&self) -> Option<fn(&str) -> Option<NodeInfo>> {
+        None
+    }
+
+    // push (node:control, value) pairs to the node's Props
+    fn set_params(&mut self, id: &str, params: &[(String, f64)]) -> Result<Pushed, String>;
+
+    // read the node's current (node:control, value) pairs back
+    fn read_params(&mut self, id: &str) -> Result<Vec<(String, f64)>, String>;
+
+    // called on every wakeup of the main loop so backends with queued work
+    // can make progress without a new update coming in. returns the round-trip
+    // time in ms once a queued push is acknowledged, or the error it ran into
+    fn poll(&mut self) -> Result<Option<f64>, String> {
+        Ok(None)
+    }
+
+    // whether poll has anything to do; the main loop then wakes up for it
+    // even when nothing else is going on
+    fn busy(&self) -> bool {
+        false
+    }
+}
+
+// json payload for 'pw-cli set-param <id> Props'
+pub fn params_json(params: &[(String, f64)]) -> String {
+    let fields: Vec<String> = params
+        .iter()
+        .map(|(name, value)| format!("\"{}\", {:.2}", name, value))
+        .collect();
+    format!("{{ \"params\": [ {} ] }}", fields.join(", "))
+}
+
+// ==============================================================================
+// pw-cli
+// ==============================================================================
+
+// give up on a pw-cli set-param that hasn't exited after this long
+const PW_CLI_TIMEOUT: Duration = Duration::from_secs(1);
+
+// spawns pw-cli for every call (pw-dump for discovery). pushes don't wait
+// for pw-cli to finish, poll reaps them and reports the ones that failed.
+#[derive(Default)]
+pub struct PwCliBackend {
+    running: Vec<(Child, Instant)>,
+}
+
+// runs pw-dump and parses the whole graph, tens of ms on a busy system
+fn pwdump_lookup(node_name: &str) -> Option<NodeInfo> {
+    pwdump::discover(node_name).ok().flatten()
+}
+
+impl OutputBackend for PwCliBackend {
+    fn name(&self) -> &'static str {
+        "pw-cli"
+    }
+
+    fn find_node(&mut self, node_name: &str) -> Option<NodeInfo> {
+        pwdump_lookup(node_name)
+    }
+
+    fn background_lookup(&self) -> Option<fn(&str) -> Option<NodeInfo>> {
+        Some(pwdump_lookup)
+    }
+
+    fn set_params(&mut self, id: &str, params: &[(String, f64)]) -> Result<Pushed, String> {
+        let json_payload = params_json(params);
+
+        // spawn async to prevent frame drops, poll checks how it exited
+        // redirect stdout/stderr to null to prevent tui artifacts
+        let child = Command::new("pw-cli")
+            .args(["set-param", id, "Props", &json_payload])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to spawn pw-cli: {}", e))?;
+        self.running.push((child, Instant::now()));
+        Ok(Pushed::Queued)
+    }
+
+    fn read_params(&mut self, id: &str) -> Result<Vec<(String, f64)>, String> {
+        let output = Command::new("pw-cli")
+            .args(["enum-params", id, "Props"])
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|e| format!("Failed to run pw-cli: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("pw-cli enum-params {} failed", id));
+        }
+        Ok(parse_enum_params(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    // reaps finished set-params without blocking: the first failure, else
+    // how long the newest successful one took
+    fn poll(&mut self) -> Result<Option<f64>, String> {
+        let mut done = Ok(None);
+        let mut still_running = Vec::new();
+        for (mut child, started) in self.running.drain(..) {
+            let outcome = match child.try_wait() {
+                Ok(Some(status)) if status.success() => Ok(started.elapsed().as_secs_f64() * 1000.0),
+                Ok(Some(status)) => Err(format!("pw-cli set-param failed ({})", status)),
+                Ok(None) if started.elapsed() > PW_CLI_TIMEOUT => {
+                    child.kill().ok();
+                    child.wait().ok();
+                    Err("pw-cli set-param did not finish".to_string())
+                }
+                Ok(None) => {
+                    still_running.push((child, started));
+                    continue;
+                }
+                Err(e) => Err(format!("Failed to wait for pw-cli: {}", e)),
+            };
+            match outcome {
+                Ok(latency) if done.is_ok() => done = Ok(Some(latency)),
+                Err(e) if done.is_ok() => done = Err(e),
+                _ => {}
+            }
+        }
+        self.running = still_running;
+        done
+    }
+
+    fn busy(&self) -> bool {
+        !self.running.is_empty()
+    }
+}
+
+// pull the name/value pairs out of the 'params' struct in a pod dump:
+//
+//   Prop: key Spa:Pod:Object:Param:Props:params (524289), flags 00000000
+//     Struct: size 1176, n_fields 24
+//       String "spat_left:Azimuth"
+//       Float 45.000000
+fn parse_enum_params(text: &str) -> Vec<(String, f64)> {
+    let mut params = Vec::new();
+    let mut pending_name: Option<String> = None;
+
+    for line in text.lines() {
+        let trim = line.trim();
+        if let Some(rest) = trim.strip_prefix("String ") {
+            pending_name = Some(rest.trim_matches('"').to_string());
+            continue;
+        }
+        if let Some(name) = pending_name.take() {
+            let mut parts = trim.split_whitespace();
+            let kind = parts.next().unwrap_or("");
+            if matches!(kind, "Float" | "Double" | "Int" | "Long") {
+                if let Some(value) = parts.next().and_then(|v| v.parse::<f64>().ok()) {
+                    params.push((name, value));
+                }
+            }
+        }
+    }
+    params
+}
+
+// ==============================================================================
+// pw-cli session
+// ==============================================================================
+
+// give up on an unacknowledged push after this long and restart pw-cli
+const SESSION_ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+// pw-cli answers unknown commands with an error that echoes the command,
+// so an unknown marker after every set-param tells us when it was handled
+const SESSION_ACK_MARKER: &str = "spatial-track-ack-";
+
+// keeps one interactive pw-cli running and writes set-param lines to its stdin.
+// only one push is in flight at a time; anything that comes in meanwhile
+// replaces the queued one, so a slow pipewire never builds up stale updates.
+pub struct PwCliSessionBackend {
+    session: Option<PwCliSession>,
+    pending: Option<(String, Vec<(String, f64)>)>,
+    acked: Option<f64>, // round-trip time of an ack poll hasn't reported yet
+}
+
+impl PwCliSessionBackend {
+    pub fn new() -> Self {
+        Self { session: None, pending: None, acked: None }
+    }
+
+    // collect acks and send the queued push once the previous one is done
+    fn pump(&mut self) -> Result<(), String> {
+        if let Some(session) = &mut self.session {
+            match session.drain() {
+                Ok(Some(rtt)) => self.acked = Some(rtt),
+                Ok(None) => {}
+                Err(e) => {
+                    // dead or stuck: drop it and start over on the next push
+                    self.session = None;
+                    return Err(e);
+                }
+            }
+        }
+
+        if self.session.as_ref().is_some_and(|s| s.in_flight.is_some()) {
+            return Ok(());
+        }
+
+        if let Some((id, params)) = self.pending.take() {
+            if self.session.is_none() {
+                self.session = Some(PwCliSession::spawn()?);
+            }
+            if let Some(session) = &mut self.session {
+                if let Err(e) = session.send(&id, &params) {
+                    self.session = None;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OutputBackend for PwCliSessionBackend {
+    fn name(&self) -> &'static str {
+        "pw-cli-session"
+    }
+
+    fn find_node(&mut self, node_name: &str) -> Option<NodeInfo> {
+        pwdump_lookup(node_name)
+    }
+
+    fn background_lookup(&self) -> Option<fn(&str) -> Option<NodeInfo>> {
+        Some(pwdump_lookup)
+    }
+
+    // only queues the push, poll reports the ack or the error pw-cli gave.
+    // an error here belongs to an earlier push
+    fn set_params(&mut self, id: &str, params: &[(String, f64)]) -> Result<Pushed, String> {
+        self.pending = Some((id.to_string(), params.to_vec()));
+        self.pump()?;
+        Ok(Pushed::Queued)
+    }
+
+    fn read_params(&mut self, id: &str) -> Result<Vec<(String, f64)>, String> {
+        PwCliBackend::default().read_params(id)
+    }
+
+    fn poll(&mut self) -> Result<Option<f64>, String> {
+        self.pump()?;
+        Ok(self.acked.take())
+    }
+
+    // waiting for an ack, or for the one in flight before sending the next
+    fn busy(&self) -> bool {
+        self.pending.is_some() || self.acked.is_some() || self.session.as_ref().is_some_and(|s| s.in_flight.is_some())
+    }
+}
+
+struct PwCliSession {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    next_seq: u64,
+    in_flight: Option<(u64, Instant)>,
+}
+
+impl PwCliSession {
+    fn spawn() -> Result<Self, String> {
+        let mut child = Command::new("pw-cli")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start pw-cli: {}", e))?;
+
+        let stdin = child.stdin.take().ok_or("pw-cli has no stdin")?;
+
+        // errors and acks can show up on either stream, funnel both into one channel
+        let (tx, lines) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            forward_lines(stdout, tx.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forward_lines(stderr, tx);
+        }
+
+        Ok(Self { child, stdin, lines, next_seq: 0, in_flight: None })
+    }
+
+    fn send(&mut self, id: &str, params: &[(String, f64)]) -> Result<(), String> {
+        self.next_seq += 1;
+        let commands = format!(
+            "set-param {} Props {}\n{}{}\n",
+            id,
+            params_json(params),
+            SESSION_ACK_MARKER,
+            self.next_seq
+        );
+        self.stdin
+            .write_all(commands.as_bytes())
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("Failed to write to pw-cli: {}", e))?;
+        self.in_flight = Some((self.next_seq, Instant::now()));
+        Ok(())
+    }
+
+    // read everything pw-cli said so far, returns the round-trip time
+    // if the in-flight push got acknowledged
+    fn drain(&mut self) -> Result<Option<f64>, String> {
+        let mut acked = None;
+        loop {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err("pw-cli exited".to_string()),
+            };
+
+            if let Some(pos) = line.find(SESSION_ACK_MARKER) {
+                let seq: u64 = line[pos + SESSION_ACK_MARKER.len()..]
+                    .chars()
+                    .take_while(|c| c.is_ascii_digit())
+                    .collect::<String>()
+                    .parse()
+                    .unwrap_or(0);
+                if let Some((in_flight, sent_at)) = self.in_flight {
+                    if seq >= in_flight {
+                        acked = Some(sent_at.elapsed().as_secs_f64() * 1000.0);
+                        self.in_flight = None;
+                    }
+                }
+            } else if line.to_lowercase().contains("error") {
+                self.in_flight = None;
+                return Err(line.trim().to_string());
+            }
+        }
+
+        if let Some((_, sent_at)) = self.in_flight {
+            if sent_at.elapsed() > SESSION_ACK_TIMEOUT {
+                return Err("pw-cli did not acknowledge the last update".to_string());
+            }
+        }
+        Ok(acked)
+    }
+}
+
+impl Drop for PwCliSession {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn forward_lines(stream: impl Read + Send + 'static, tx: Sender<String>) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+// ==============================================================================
+// dry-run
+// ==============================================================================
+
+// never touches pipewire, writes every payload to a log file instead
+pub struct DryRunBackend {
+    log: File,
+    last_params: Vec<(String, f64)>,
+}
+
+impl DryRunBackend {
+    pub fn new(path: &str) -> Result<Self, String> {
+        let log = File::create(path).map_err(|e| format!("Failed to create dry-run log '{}': {}", path, e))?;
+        Ok(Self { log, last_params: Vec::new() })
+    }
+}
+
+impl OutputBackend for DryRunBackend {
+    fn name(&self) -> &'static str {
+        "dry-run"
+    }
+
+    fn find_node(&mut self, node_name: &str) -> Option<NodeInfo> {
+        writeln!(self.log, "find-node {}", node_name).ok();
+        Some(NodeInfo::new("0", node_name))
+    }
+
+    fn set_params(&mut self, id: &str, params: &[(String, f64)]) -> Result<Pushed, String> {
+        let start = Instant::now();
+        writeln!(self.log, "set-param {} Props {}", id, params_json(params))
+            .map_err(|e| format!("Failed to write dry-run log: {}", e))?;
+        self.last_params = params.to_vec();
+        Ok(Pushed::Done(start.elapsed().as_secs_f64() * 1000.0))
+    }
+
+    fn read_params(&mut self, _id: &str) -> Result<Vec<(String, f64)>, String> {
+        Ok(self.last_params.clone())
+    }
+}
+
+// ==============================================================================
+// mock (tests)
+// ==============================================================================
+
+// records every call so tests can check what the main loop pushed
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockBackend {
+    pub node_id: Option<String>,
+    pub fail_pushes: bool,
+    pub queue_pushes: bool,                       // answer like the pw-cli session does
+    pub polls: Vec<Result<Option<f64>, String>>, // what the next polls return, in order
+    pub lookups: Vec<String>,
+    pub pushes: Vec<(String, Vec<(String, f64)>)>,
+}
+
+#[cfg(test)]
+impl OutputBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn find_node(&mut self, node_name: &str) -> Option<NodeInfo> {
+        self.lookups.push(node_name.to_string());
+        self.node_id.as_deref().map(|id| NodeInfo::new(id, node_name))
+    }
+
+    fn set_params(&mut self, id: &str, params: &[(String, f64)]) -> Result<Pushed, String> {
+        if self.fail_pushes {
+            return Err(format!("node {} rejected params", id));
+        }
+        self.pushes.push((id.to_string(), params.to_vec()));
+        Ok(if self.queue_pushes { Pushed::Queued } else { Pushed::Done(0.0) })
+    }
+
+    fn read_params(&mut self, id: &str) -> Result<Vec<(String, f64)>, String> {
+        self.pushes
+            .iter()
+            .rev()
+            .find(|(pushed_id, _)| pushed_id == id)
+            .map(|(_, params)| params.clone())
+            .ok_or_else(|| format!("node {} has no params", id))
+    }
+
+    fn poll(&mut self) -> Result<Option<f64>, String> {
+        if self.polls.is_empty() {
+            return Ok(None);
+        }
+        self.polls.remove(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_json_matches_pw_cli_syntax() {
+        let params = vec![
+            ("spat_left:Azimuth".to_string(), 45.0),
+            ("final_mix_l:Gain 1".to_string(), 0.5),
+        ];
+        assert_eq!(
+            params_json(&params),
+            "{ \"params\": [ \"spat_left:Azimuth\", 45.00, \"final_mix_l:Gain 1\", 0.50 ] }"
+        );
+    }
+
+    #[test]
+    fn link_group_peer_is_the_other_side() {
+        let nodes = || {
+            vec![
+                ("40".to_string(), "effect_input.spatializer", Some("fc-1")),
+                ("41".to_string(), "effect_output.spatializer", Some("fc-1")),
+                ("50".to_string(), "some_sink", None),
+            ]
+        };
+        assert_eq!(link_group_peer("40", Some("fc-1"), nodes()), Some(("41".to_string(), "effect_output.spatializer".to_string())));
+        assert_eq!(link_group_peer("50", None, nodes()), None);
+        assert_eq!(link_group_peer("40", Some("fc-2"), nodes()), None);
+    }
+
+    #[test]
+    fn pw_cli_failures_are_reported_by_poll() {
+        let wait = |backend: &mut PwCliBackend| {
+            let start = Instant::now();
+            loop {
+                let result = backend.poll();
+                if !backend.busy() || start.elapsed() > Duration::from_secs(5) {
+                    return result;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        };
+        let spawn = |program: &str| (Command::new(program).spawn().unwrap(), Instant::now());
+
+        let mut backend = PwCliBackend::default();
+        backend.running.push(spawn("true"));
+        assert!(matches!(wait(&mut backend), Ok(Some(_))));
+
+        // a failed set-param (node gone, bad id) exits non-zero
+        backend.running.push(spawn("false"));
+        assert!(wait(&mut backend).unwrap_err().starts_with("pw-cli set-param failed"));
+        assert!(!backend.busy());
+    }
+
+    #[test]
+    fn parses_params_struct_from_pod_dump() {
+        let dump = r#"
+  Object: size 232, type Spa:Pod:Object:Param:Props (262146), id Spa:Enum:ParamId:Props (2)
+    Prop: key Spa:Pod:Object:Param:Props:params (524289), flags 00000000
+      Struct: size 184, n_fields 4
+        String "spat_left:Azimuth"
+        Float 45.000000
+        String "mix_l:Gain 1"
+        Float 0.500000
+"#;
+        assert_eq!(
+            parse_enum_params(dump),
+            vec![("spat_left:Azimuth".to_string(), 45.0), ("mix_l:Gain 1".to_string(), 0.5)]
+        );
+    }
+}
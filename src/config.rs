@@ -0,0 +1,683 @@
+// ==============================================================================
+// CONFIG FILE
+// ==============================================================================
+//
+// ~/.config/spatial-track/config.toml. every key is optional and falls back
+// to the built-in default; values that make no sense are rejected with a
+// message that names the key.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::filter::{FilterKind, FilterParams, Param};
+use crate::graph;
+use crate::paths;
+
+// opentrack's default 'UDP over network' target
+const DEFAULT_LISTEN_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 4242);
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub tracker: TrackerConfig,
+    pub smoothing: SmoothingConfig,
+    pub prediction: PredictionConfig,
+    pub recenter: RecenterConfig,
+    pub translation: TranslationConfig,
+    pub radius: RadiusConfig,
+    pub reverb: ReverbConfig,
+    pub width: WidthConfig,
+    // cycled with L; the built-in ones plus [[layouts]] from the file
+    // (see parse), a file layout with a built-in's name replaces it
+    pub layouts: Vec<Layout>,
+    pub pipewire: PipewireConfig,
+    // recalled with keys 1-9, in file order
+    pub presets: Vec<Preset>,
+    // where this config was loaded from, presets are saved back there
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tracker: TrackerConfig::default(),
+            smoothing: SmoothingConfig::default(),
+            prediction: PredictionConfig::default(),
+            recenter: RecenterConfig::default(),
+            translation: TranslationConfig::default(),
+            radius: RadiusConfig::default(),
+            reverb: ReverbConfig::default(),
+            width: WidthConfig::default(),
+            layouts: builtin_layouts(),
+            pipewire: PipewireConfig::default(),
+            presets: Vec::new(),
+            path: PathBuf::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackerConfig {
+    // where to receive opentrack packets
+    #[serde(deserialize_with = "deserialize_listen_addr")]
+    pub listen: SocketAddr,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self { listen: DEFAULT_LISTEN_ADDR }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmoothingConfig {
+    // higher = smoother but more latency (0.0 - 0.99)
+    pub factor: f64,
+    // min time between updates (20ms = ~50fps)
+    pub update_rate_ms: u64,
+    // only send command if angle changes by this many degrees
+    pub change_threshold: f64,
+    // pose filters run in this order on every packet, see filter.rs
+    pub filters: Vec<FilterKind>,
+    // degrees of movement the deadzone filter ignores
+    pub deadzone: f64,
+    // packets the median filter looks at
+    pub median_window: usize,
+    pub one_euro: OneEuroConfig,
+}
+
+impl Default for SmoothingConfig {
+    fn default() -> Self {
+        Self {
+            factor: 0.65,
+            update_rate_ms: 20,
+            change_threshold: 0.5,
+            filters: vec![FilterKind::Ema],
+            deadzone: 0.2,
+            median_window: 3,
+            one_euro: OneEuroConfig::default(),
+        }
+    }
+}
+
+impl SmoothingConfig {
+    // the filter parameters to start from
+    pub fn params(&self) -> FilterParams {
+        FilterParams {
+            factor: self.factor,
+            deadzone: self.deadzone,
+            min_cutoff: self.one_euro.min_cutoff,
+            beta: self.one_euro.beta,
+            d_cutoff: self.one_euro.d_cutoff,
+            median_window: self.median_window,
+        }
+    }
+}
+
+// adaptive low-pass, see filter::OneEuro
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OneEuroConfig {
+    pub min_cutoff: f64, // Hz when still, lower = less jitter
+    pub beta: f64,       // cutoff increase per degree/second, higher = less lag
+    pub d_cutoff: f64,   // Hz, for the speed estimate
+}
+
+impl Default for OneEuroConfig {
+    fn default() -> Self {
+        Self { min_cutoff: 1.0, beta: 0.05, d_cutoff: 1.0 }
+    }
+}
+
+// extrapolate the head ahead to make up for latency, see filter::Predictor
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PredictionConfig {
+    // how far ahead, 0 = off
+    pub lookahead_ms: f64,
+    // never lead the measured pose by more than this
+    pub max_degrees: f64,
+}
+
+impl Default for PredictionConfig {
+    fn default() -> Self {
+        Self { lookahead_ms: 0.0, max_degrees: 15.0 }
+    }
+}
+
+// straight ahead follows the listener, see recenter.rs
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecenterConfig {
+    // ease the center towards where the head has been facing
+    pub auto: bool,
+    // after the head stayed within `tolerance` degrees for this long
+    pub hold_secs: f64,
+    pub tolerance: f64,
+    // degrees per second the center moves by
+    pub rate: f64,
+}
+
+impl Default for RecenterConfig {
+    fn default() -> Self {
+        Self { auto: false, hold_secs: 5.0, tolerance: 15.0, rate: 5.0 }
+    }
+}
+
+// where the head is as well as how it's turned (6dof), for parallax
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranslationConfig {
+    pub enabled: bool,
+    // tracker units to metres; opentrack sends centimetres
+    pub scale: f64,
+}
+
+impl Default for TranslationConfig {
+    fn default() -> Self {
+        Self { enabled: false, scale: 0.01 }
+    }
+}
+
+// default radius, can change at runtime
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RadiusConfig {
+    pub default: f64,
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+}
+
+impl Default for RadiusConfig {
+    fn default() -> Self {
+        Self { default: 1.5, min: 0.1, max: 10.0, step: 0.1 }
+    }
+}
+
+// dynamic reverb wet/dry mix depending on distance
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReverbConfig {
+    pub min: f64, // closest
+    pub max: f64, // farthest
+}
+
+impl Default for ReverbConfig {
+    fn default() -> Self {
+        Self { min: 0.05, max: 0.60 }
+    }
+}
+
+// where the virtual speakers sit, as heard: azimuth in degrees with
+// + = left and 0 = straight ahead, elevation + = up
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    pub name: String,
+    pub speakers: Vec<Speaker>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Speaker {
+    pub channel: String, // sink channel it plays, e.g. "FL"
+    pub azimuth: f64,
+    #[serde(default)]
+    pub elevation: f64,
+    #[serde(default = "unit_gain")]
+    pub gain: f64,
+}
+
+fn unit_gain() -> f64 {
+    1.0
+}
+
+impl Layout {
+    fn stereo(name: &str, angle: f64) -> Self {
+        let speaker = |channel: &str, azimuth| Speaker { channel: channel.to_string(), azimuth, elevation: 0.0, gain: 1.0 };
+        Self { name: name.to_string(), speakers: vec![speaker("FL", angle), speaker("FR", -angle)] }
+    }
+
+    pub fn speaker(&self, channel: &str) -> Option<&Speaker> {
+        self.speakers.iter().find(|s| s.channel == channel)
+    }
+}
+
+// front: speakers ahead at +-45 (wider than a real stereo pair, less focus);
+// back: behind at +-135
+pub fn builtin_layouts() -> Vec<Layout> {
+    vec![Layout::stereo("front", 45.0), Layout::stereo("back", 135.0)]
+}
+
+// stereo width control: adjusts speaker separation
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WidthConfig {
+    pub default: f64, // 100% = full separation
+    pub min: f64,     // 30% = narrow (more focused)
+    pub max: f64,     // 150% = extra wide (very diffuse)
+    pub step: f64,
+}
+
+impl Default for WidthConfig {
+    fn default() -> Self {
+        Self { default: 1.0, min: 0.3, max: 1.5, step: 0.1 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipewireConfig {
+    // node name to search for in pipewire
+    pub node_name: String,
+    // sink format the graph is generated for: "stereo", "5.1", "7.1", "5.1.2" or "7.1.4"
+    pub channels: String,
+    // level of the LFE channel mixed into both ears (multichannel only)
+    pub lfe_gain: f64,
+}
+
+impl Default for PipewireConfig {
+    fn default() -> Self {
+        Self { node_name: "effect_input.spatializer".to_string(), channels: "stereo".to_string(), lfe_gain: 0.5 }
+    }
+}
+
+// a named bundle of runtime settings; keys left out keep their current value
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reverb: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smoothing: Option<f64>,
+}
+
+// one per number key
+pub const MAX_PRESETS: usize = 9;
+
+impl Config {
+    pub fn format(&self) -> &'static graph::Format {
+        graph::format(&self.pipewire.channels).unwrap_or(&graph::FORMATS[0])
+    }
+
+    // values serde can't rule out on its own
+    pub fn validate(&self) -> Result<(), String> {
+        let s = &self.smoothing;
+        check_param("smoothing.factor", Param::Factor, s.factor)?;
+        if s.update_rate_ms == 0 || s.update_rate_ms > 1000 {
+            return Err(format!("smoothing.update_rate_ms must be between 1 and 1000, got {}", s.update_rate_ms));
+        }
+        check_range("smoothing.change_threshold", s.change_threshold, 0.0, 45.0)?;
+        check_param("smoothing.deadzone", Param::Deadzone, s.deadzone)?;
+        check_param("smoothing.median_window", Param::MedianWindow, s.median_window as f64)?;
+        check_param("smoothing.one_euro.min_cutoff", Param::MinCutoff, s.one_euro.min_cutoff)?;
+        check_param("smoothing.one_euro.beta", Param::Beta, s.one_euro.beta)?;
+        check_range("smoothing.one_euro.d_cutoff", s.one_euro.d_cutoff, 0.01, 30.0)?;
+
+        check_range("prediction.lookahead_ms", self.prediction.lookahead_ms, 0.0, 200.0)?;
+        check_range("prediction.max_degrees", self.prediction.max_degrees, 0.0, 90.0)?;
+
+        check_range("recenter.hold_secs", self.recenter.hold_secs, 0.5, 600.0)?;
+        check_range("recenter.tolerance", self.recenter.tolerance, 1.0, 90.0)?;
+        check_range("recenter.rate", self.recenter.rate, 0.1, 90.0)?;
+
+        check_range("translation.scale", self.translation.scale, 0.0, 10.0)?;
+
+        let r = &self.radius;
+        check_limits("radius", r.default, r.min, r.max, r.step)?;
+        if r.min <= 0.0 {
+            return Err(format!("radius.min must be greater than 0, got {}", r.min));
+        }
+
+        check_range("reverb.min", self.reverb.min, 0.0, 1.0)?;
+        check_range("reverb.max", self.reverb.max, 0.0, 1.0)?;
+        if self.reverb.min > self.reverb.max {
+            return Err(format!(
+                "reverb.min ({}) must not be greater than reverb.max ({})",
+                self.reverb.min, self.reverb.max
+            ));
+        }
+
+        let w = &self.width;
+        check_limits("width", w.default, w.min, w.max, w.step)?;
+        if w.min <= 0.0 {
+            return Err(format!("width.min must be greater than 0, got {}", w.min));
+        }
+
+        if self.pipewire.node_name.trim().is_empty() {
+            return Err("pipewire.node_name must not be empty".to_string());
+        }
+        if graph::format(&self.pipewire.channels).is_none() {
+            let known: Vec<&str> = graph::FORMATS.iter().map(|f| f.name).collect();
+            return Err(format!(
+                "pipewire.channels '{}' is not one of {}",
+                self.pipewire.channels,
+                known.join(", ")
+            ));
+        }
+        check_range("pipewire.lfe_gain", self.pipewire.lfe_gain, 0.0, 2.0)?;
+
+        if self.presets.len() > MAX_PRESETS {
+            return Err(format!("at most {} presets fit on the number keys, got {}", MAX_PRESETS, self.presets.len()));
+        }
+        for (i, preset) in self.presets.iter().enumerate() {
+            let key = |field: &str| format!("presets[{}].{}", i, field);
+            if preset.name.trim().is_empty() {
+                return Err(format!("{} must not be empty", key("name")));
+            }
+            if self.presets[..i].iter().any(|p| p.name == preset.name) {
+                return Err(format!("preset name '{}' is used twice", preset.name));
+            }
+            if let Some(radius) = preset.radius {
+                check_range(&key("radius"), radius, r.min, r.max)?;
+            }
+            if let Some(width) = preset.width {
+                check_range(&key("width"), width, w.min, w.max)?;
+            }
+            if let Some(smoothing) = preset.smoothing {
+                check_param(&key("smoothing"), Param::Factor, smoothing)?;
+            }
+            if let Some(layout) = &preset.layout {
+                if !self.layouts.iter().any(|l| &l.name == layout) {
+                    return Err(format!("{} names unknown layout '{}'", key("layout"), layout));
+                }
+            }
+        }
+
+        if self.layouts.is_empty() {
+            return Err("at least one layout is needed".to_string());
+        }
+        for (i, layout) in self.layouts.iter().enumerate() {
+            if layout.name.trim().is_empty() {
+                return Err(format!("layouts[{}].name must not be empty", i));
+            }
+            if self.layouts[..i].iter().any(|l| l.name == layout.name) {
+                return Err(format!("layout name '{}' is used twice", layout.name));
+            }
+            if layout.speakers.is_empty() {
+                return Err(format!("layout '{}' has no speakers", layout.name));
+            }
+            for (j, speaker) in layout.speakers.iter().enumerate() {
+                let key = |field: &str| format!("layout '{}' speakers[{}].{}", layout.name, j, field);
+                let known = graph::speaker_channels();
+                if !known.contains(&speaker.channel.as_str()) {
+                    return Err(format!("{} '{}' is not one of {}", key("channel"), speaker.channel, known.join(", ")));
+                }
+                if layout.speakers[..j].iter().any(|s| s.channel == speaker.channel) {
+                    return Err(format!("layout '{}' places channel {} twice", layout.name, speaker.channel));
+                }
+                check_range(&key("azimuth"), speaker.azimuth, -180.0, 180.0)?;
+                check_range(&key("elevation"), speaker.elevation, -90.0, 90.0)?;
+                check_range(&key("gain"), speaker.gain, 0.0, 2.0)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_range(key: &str, value: f64, min: f64, max: f64) -> Result<(), String> {
+    if !value.is_finite() || value < min || value > max {
+        return Err(format!("{} must be between {} and {}, got {}", key, min, max, value));
+    }
+    Ok(())
+}
+
+// a filter parameter the dashboard can tune
+fn check_param(key: &str, param: Param, value: f64) -> Result<(), String> {
+    let (min, max) = param.range();
+    check_range(key, value, min, max)
+}
+
+// a runtime-adjustable value: min <= default <= max, positive step
+fn check_limits(section: &str, default: f64, min: f64, max: f64, step: f64) -> Result<(), String> {
+    for (key, value) in [("default", default), ("min", min), ("max", max), ("step", step)] {
+        if !value.is_finite() {
+            return Err(format!("{}.{} must be a finite number, got {}", section, key, value));
+        }
+    }
+    if min > max {
+        return Err(format!("{0}.min ({1}) must not be greater than {0}.max ({2})", section, min, max));
+    }
+    if default < min || default > max {
+        return Err(format!("{0}.default ({1}) must be between {0}.min ({2}) and {0}.max ({3})", section, default, min, max));
+    }
+    if step <= 0.0 {
+        return Err(format!("{}.step must be greater than 0, got {}", section, step));
+    }
+    Ok(())
+}
+
+pub fn default_path() -> PathBuf {
+    paths::config_home().join("spatial-track/config.toml")
+}
+
+// load the config file; a missing file is only an error if it was asked for
+pub fn load(path: Option<&Path>) -> Result<Config, String> {
+    let default_path = default_path();
+    let (path, required) = match path {
+        Some(path) => (path, true),
+        None => (default_path.as_path(), false),
+    };
+
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => {
+            return Ok(Config { path: path.to_path_buf(), ..Config::default() });
+        }
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+
+    let mut config = parse(&text).map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+    config.path = path.to_path_buf();
+    Ok(config)
+}
+
+// config file contents, with the built-in layouts merged in and validated
+pub fn parse(text: &str) -> Result<Config, String> {
+    let mut config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+    let mut layouts = builtin_layouts();
+    for layout in config.layouts.drain(..) {
+        match layouts.iter_mut().find(|l| l.name == layout.name) {
+            Some(builtin) => *builtin = layout,
+            None => layouts.push(layout),
+        }
+    }
+    config.layouts = layouts;
+    config.validate()?;
+    Ok(config)
+}
+
+// write a preset into slot `index` of the [[presets]] array, appending when
+// the slot is new. edits the file in place so comments and layout survive.
+pub fn save_preset(path: &Path, index: usize, preset: &Preset) -> Result<(), String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    let mut doc: toml_edit::DocumentMut = text.parse().map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+
+    let encoded = toml::to_string(preset).map_err(|e| format!("Failed to encode preset: {}", e))?;
+    let table = encoded
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|e| format!("Failed to encode preset: {}", e))?
+        .as_table()
+        .clone();
+
+    let presets = doc
+        .entry("presets")
+        .or_insert_with(|| toml_edit::Item::ArrayOfTables(toml_edit::ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+        .ok_or_else(|| format!("'presets' in {} is not a [[presets]] list", path.display()))?;
+    if index < presets.len() {
+        *presets.get_mut(index).unwrap() = table;
+    } else if index == presets.len() {
+        presets.push(table);
+    } else {
+        return Err(format!("preset slot {} is past the end of the list", index + 1));
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    std::fs::write(path, doc.to_string()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// "ip:port", "[v6]:port", "hostname:port", a bare ip (port 4242) or a bare port (localhost)
+pub fn parse_listen_addr(text: &str) -> Result<SocketAddr, String> {
+    let text = text.trim();
+    if let Ok(addr) = text.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    if let Ok(ip) = text.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, DEFAULT_LISTEN_ADDR.port()));
+    }
+    if let Ok(port) = text.parse::<u16>() {
+        return Ok(SocketAddr::new(DEFAULT_LISTEN_ADDR.ip(), port));
+    }
+    // hostnames, e.g. localhost:4242
+    if let Some(addr) = text.to_socket_addrs().ok().and_then(|mut addrs| addrs.next()) {
+        return Ok(addr);
+    }
+    Err(format!(
+        "'{}' is not a listen address (expected e.g. 127.0.0.1:4242, [::1]:4242, 0.0.0.0 or 4242)",
+        text
+    ))
+}
+
+fn deserialize_listen_addr<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SocketAddr, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_listen_addr(&text).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_listen_address_forms() {
+        assert_eq!(parse_listen_addr("0.0.0.0:5555").unwrap(), "0.0.0.0:5555".parse().unwrap());
+        assert_eq!(parse_listen_addr("[::1]:4243").unwrap(), "[::1]:4243".parse().unwrap());
+        assert_eq!(parse_listen_addr("::").unwrap(), "[::]:4242".parse().unwrap());
+        assert_eq!(parse_listen_addr("192.168.1.20").unwrap(), "192.168.1.20:4242".parse().unwrap());
+        assert_eq!(parse_listen_addr("4300").unwrap(), "127.0.0.1:4300".parse().unwrap());
+        assert!(parse_listen_addr("localhost:99999").is_err());
+    }
+
+    #[test]
+    fn missing_keys_fall_back_to_defaults() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.tracker.listen, DEFAULT_LISTEN_ADDR);
+
+        let config: Config = toml::from_str("[tracker]\nlisten = \"[::]:4242\"").unwrap();
+        assert_eq!(config.tracker.listen, "[::]:4242".parse().unwrap());
+    }
+
+    #[test]
+    fn rejects_bad_listen_address() {
+        let err = toml::from_str::<Config>("[tracker]\nlisten = \"nowhere\"").err().unwrap();
+        assert!(err.to_string().contains("not a listen address"));
+    }
+
+    #[test]
+    fn partial_sections_keep_other_defaults() {
+        let config: Config = toml::from_str("[radius]\nmax = 5.0\n[pipewire]\nnode_name = \"my_sink\"").unwrap();
+        assert_eq!(config.radius.max, 5.0);
+        assert_eq!(config.radius.default, 1.5);
+        assert_eq!(config.smoothing.factor, 0.65);
+        assert_eq!(config.pipewire.node_name, "my_sink");
+        assert!(config.validate().is_ok());
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn example_config_matches_defaults() {
+        let example = parse(include_str!("../conf/spatial-track.toml")).unwrap();
+        let defaults = Config::default();
+        assert_eq!(example.tracker.listen, defaults.tracker.listen);
+        assert_eq!(example.smoothing.factor, defaults.smoothing.factor);
+        assert_eq!(example.smoothing.filters, defaults.smoothing.filters);
+        assert_eq!(example.smoothing.params(), defaults.smoothing.params());
+        assert_eq!(example.prediction.lookahead_ms, defaults.prediction.lookahead_ms);
+        assert_eq!(example.recenter.auto, defaults.recenter.auto);
+        assert_eq!(example.recenter.hold_secs, defaults.recenter.hold_secs);
+        assert_eq!(example.translation.enabled, defaults.translation.enabled);
+        assert_eq!(example.translation.scale, defaults.translation.scale);
+        assert_eq!(example.radius.max, defaults.radius.max);
+        assert_eq!(example.layouts.len(), defaults.layouts.len());
+        assert_eq!(example.width.min, defaults.width.min);
+        assert_eq!(example.pipewire.node_name, defaults.pipewire.node_name);
+    }
+
+    #[test]
+    fn file_layouts_extend_and_replace_builtins() {
+        let config = parse(
+            "[[layouts]]\nname = \"studio\"\nspeakers = [\n  { channel = \"FL\", azimuth = 30.0 },\n  { channel = \"FR\", azimuth = -30.0, gain = 0.8 },\n]\n\n[[layouts]]\nname = \"back\"\nspeakers = [{ channel = \"FL\", azimuth = 120.0 }, { channel = \"FR\", azimuth = -120.0 }]\n",
+        )
+        .unwrap();
+        let names: Vec<&str> = config.layouts.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["front", "back", "studio"]);
+        assert_eq!(config.layouts[1].speakers[0].azimuth, 120.0);
+        assert_eq!(config.layouts[2].speaker("FR").unwrap().gain, 0.8);
+        assert_eq!(config.layouts[2].speaker("FL").unwrap().gain, 1.0);
+    }
+
+    #[test]
+    fn saving_presets_keeps_the_rest_of_the_file() {
+        let path = std::env::temp_dir().join(format!("spatial-track-presets-{}.toml", std::process::id()));
+        std::fs::write(&path, "# my setup\n[radius]\nmax = 5.0\n\n[[presets]]\nname = \"movie\"\nradius = 3.0\n").unwrap();
+
+        let music = Preset { name: "music".to_string(), width: Some(1.2), ..Default::default() };
+        save_preset(&path, 1, &music).unwrap();
+        let movie = Preset { name: "movie".to_string(), radius: Some(4.0), layout: Some("back".to_string()), ..Default::default() };
+        save_preset(&path, 0, &movie).unwrap();
+        assert!(save_preset(&path, 5, &music).is_err());
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let config = load(Some(&path)).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert!(text.starts_with("# my setup"));
+        assert_eq!(config.radius.max, 5.0);
+        assert_eq!(config.presets.len(), 2);
+        assert_eq!(config.presets[0].radius, Some(4.0));
+        assert_eq!(config.presets[0].layout.as_deref(), Some("back"));
+        assert_eq!(config.presets[1].name, "music");
+        assert_eq!(config.presets[1].width, Some(1.2));
+    }
+
+    #[test]
+    fn invalid_values_name_the_key() {
+        let check = |text: &str| parse(text).err().unwrap();
+        assert!(check("[smoothing]\nfactor = 1.5").contains("smoothing.factor"));
+        assert!(check("[radius]\nmin = 3.0").contains("radius.default"));
+        assert!(check("[width]\nstep = 0.0").contains("width.step"));
+        assert!(check("[reverb]\nmin = 0.8\nmax = 0.2").contains("reverb.min"));
+        assert!(check("[[layouts]]\nname = \"x\"\nspeakers = [{ channel = \"FL\", azimuth = nan }]").contains("speakers[0].azimuth"));
+        assert!(check("[[layouts]]\nname = \"x\"\nspeakers = [{ channel = \"XX\", azimuth = 0 }]").contains("channel 'XX'"));
+        assert!(check("[[layouts]]\nname = \"x\"\nspeakers = []").contains("no speakers"));
+        assert!(check("[[presets]]\nname = \"a\"\nlayout = \"nope\"").contains("unknown layout"));
+        assert!(check("[pipewire]\nchannels = \"9.1\"").contains("pipewire.channels"));
+        assert!(check("[prediction]\nlookahead_ms = -5").contains("prediction.lookahead_ms"));
+        assert!(check("[smoothing.one_euro]\nmin_cutoff = 0").contains("smoothing.one_euro.min_cutoff"));
+        assert!(check("[smoothing]\nfilters = [\"kalman\"]").contains("kalman"));
+        assert!(check("[[presets]]\nname = \"far\"\nradius = 99.0").contains("presets[0].radius"));
+        assert!(check("[[presets]]\nname = \"a\"\n[[presets]]\nname = \"a\"").contains("twice"));
+
+        let err = toml::from_str::<Config>("[smoothing]\nfactr = 0.5").err().unwrap();
+        assert!(err.to_string().contains("factr"));
+    }
+}
@@ -0,0 +1,538 @@
+// ==============================================================================
+// POSE FILTERS
+// ==============================================================================
+//
+// every packet runs through [smoothing] filters in order before the speakers
+// are placed. each filter keeps its own history but reads its parameters
+// fresh on every call, so they can be tuned from the dashboard while running.
+// all of them work on the circle (see rotation::angle_delta) so a yaw
+// wrapping from +179 to -179 is a 2 degree move.
+
+use serde::Deserialize;
+
+use crate::rotation::{angle_delta, wrap_degrees};
+
+// head orientation in degrees, as opentrack sends it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pose {
+    pub yaw: f64,
+    pub pitch: f64,
+    pub roll: f64,
+}
+
+impl Pose {
+    pub fn new(yaw: f64, pitch: f64, roll: f64) -> Self {
+        Self { yaw, pitch, roll }
+    }
+
+    fn axes(self) -> [f64; 3] {
+        [self.yaw, self.pitch, self.roll]
+    }
+
+    fn from_axes([yaw, pitch, roll]: [f64; 3]) -> Self {
+        Self { yaw, pitch, roll }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    Deadzone,
+    Ema,
+    OneEuro,
+    Median,
+}
+
+impl FilterKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterKind::Deadzone => "deadzone",
+            FilterKind::Ema => "ema",
+            FilterKind::OneEuro => "one_euro",
+            FilterKind::Median => "median",
+        }
+    }
+
+    // what the dashboard can tune for this filter
+    pub fn params(self) -> &'static [Param] {
+        match self {
+            FilterKind::Deadzone => &[Param::Deadzone],
+            FilterKind::Ema => &[Param::Factor],
+            FilterKind::OneEuro => &[Param::MinCutoff, Param::Beta],
+            FilterKind::Median => &[Param::MedianWindow],
+        }
+    }
+}
+
+// the runtime-tunable filter parameters (see Settings)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilterParams {
+    pub factor: f64,          // ema: higher = smoother
+    pub deadzone: f64,        // degrees of movement ignored around the held pose
+    pub min_cutoff: f64,      // one euro: cutoff in Hz when still, lower = less jitter
+    pub beta: f64,            // one euro: how fast the cutoff rises with speed, higher = less lag
+    pub d_cutoff: f64,        // one euro: cutoff for the speed estimate, Hz (config only)
+    pub median_window: usize, // packets
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Param {
+    Factor,
+    Deadzone,
+    MinCutoff,
+    Beta,
+    MedianWindow,
+}
+
+impl Param {
+    pub fn name(self) -> &'static str {
+        match self {
+            Param::Factor => "factor",
+            Param::Deadzone => "deadzone",
+            Param::MinCutoff => "min_cutoff",
+            Param::Beta => "beta",
+            Param::MedianWindow => "window",
+        }
+    }
+
+    // the allowed values, shared by config validation, state restore and the keys
+    pub fn range(self) -> (f64, f64) {
+        match self {
+            Param::Factor => (0.0, 0.99),
+            Param::Deadzone => (0.0, 10.0),
+            Param::MinCutoff => (0.01, 30.0),
+            Param::Beta => (0.0, 10.0),
+            Param::MedianWindow => (1.0, 31.0),
+        }
+    }
+
+    fn step(self) -> f64 {
+        match self {
+            Param::Factor => 0.05,
+            Param::Deadzone => 0.1,
+            Param::MinCutoff => 0.1,
+            Param::Beta => 0.01,
+            Param::MedianWindow => 1.0,
+        }
+    }
+
+    pub fn get(self, params: &FilterParams) -> f64 {
+        match self {
+            Param::Factor => params.factor,
+            Param::Deadzone => params.deadzone,
+            Param::MinCutoff => params.min_cutoff,
+            Param::Beta => params.beta,
+            Param::MedianWindow => params.median_window as f64,
+        }
+    }
+
+    pub fn set(self, params: &mut FilterParams, value: f64) {
+        let (min, max) = self.range();
+        let value = value.clamp(min, max);
+        match self {
+            Param::Factor => params.factor = value,
+            Param::Deadzone => params.deadzone = value,
+            Param::MinCutoff => params.min_cutoff = value,
+            Param::Beta => params.beta = value,
+            Param::MedianWindow => params.median_window = value.round() as usize,
+        }
+    }
+
+    // one step up (or down), rounded so repeated steps don't drift
+    pub fn adjust(self, params: &mut FilterParams, up: bool) {
+        let step = self.step();
+        let value = self.get(params) + if up { step } else { -step };
+        self.set(params, (value / step).round() * step);
+    }
+
+    pub fn format(self, params: &FilterParams) -> String {
+        match self {
+            Param::Factor => format!("{:.2}", params.factor),
+            Param::Deadzone => format!("{:.1}°", params.deadzone),
+            Param::MinCutoff => format!("{:.2} Hz", params.min_cutoff),
+            Param::Beta => format!("{:.2}", params.beta),
+            Param::MedianWindow => format!("{}", params.median_window),
+        }
+    }
+}
+
+// every (filter, parameter) pair of a chain, in order, for the dashboard to
+// step through
+pub fn tunables(kinds: &[FilterKind]) -> Vec<(FilterKind, Param)> {
+    let mut tunables: Vec<(FilterKind, Param)> = Vec::new();
+    for &kind in kinds {
+        for &param in kind.params() {
+            // the same filter twice shares its parameters
+            if !tunables.iter().any(|&(_, p)| p == param) {
+                tunables.push((kind, param));
+            }
+        }
+    }
+    tunables
+}
+
+pub trait PoseFilter {
+    // the filtered pose for a new sample, `dt` seconds after the last one
+    fn apply(&mut self, pose: Pose, dt: f64, params: &FilterParams) -> Pose;
+}
+
+pub struct FilterChain {
+    filters: Vec<Box<dyn PoseFilter>>,
+}
+
+impl FilterChain {
+    pub fn new(kinds: &[FilterKind]) -> Self {
+        let filters = kinds
+            .iter()
+            .map(|kind| -> Box<dyn PoseFilter> {
+                match kind {
+                    FilterKind::Deadzone => Box::new(Deadzone::default()),
+                    FilterKind::Ema => Box::new(Ema::default()),
+                    FilterKind::OneEuro => Box::new(OneEuro::default()),
+                    FilterKind::Median => Box::new(Median::default()),
+                }
+            })
+            .collect();
+        Self { filters }
+    }
+
+    pub fn apply(&mut self, pose: Pose, dt: f64, params: &FilterParams) -> Pose {
+        self.filters.iter_mut().fold(pose, |pose, filter| filter.apply(pose, dt, params))
+    }
+}
+
+// ==============================================================================
+// filters
+// ==============================================================================
+
+// holds the pose until it moves further than the deadzone, then follows it at
+// the deadzone's distance, so there's no jump when it lets go
+#[derive(Default)]
+pub struct Deadzone {
+    held: Option<Pose>,
+}
+
+impl PoseFilter for Deadzone {
+    fn apply(&mut self, pose: Pose, _dt: f64, params: &FilterParams) -> Pose {
+        let held = match self.held {
+            None => pose,
+            Some(held) => {
+                let mut axes = held.axes();
+                for (held, raw) in axes.iter_mut().zip(pose.axes()) {
+                    let delta = angle_delta(*held, raw);
+                    if delta.abs() > params.deadzone {
+                        *held = wrap_degrees(*held + delta - params.deadzone * delta.signum());
+                    }
+                }
+                Pose::from_axes(axes)
+            }
+        };
+        self.held = Some(held);
+        held
+    }
+}
+
+// exponential moving average; each sample moves the output (1 - factor) of
+// the way towards it
+#[derive(Default)]
+pub struct Ema {
+    last: Option<Pose>,
+}
+
+impl PoseFilter for Ema {
+    fn apply(&mut self, pose: Pose, _dt: f64, params: &FilterParams) -> Pose {
+        let smoothed = match self.last {
+            None => pose,
+            Some(last) => {
+                let mut axes = last.axes();
+                for (last, raw) in axes.iter_mut().zip(pose.axes()) {
+                    *last = wrap_degrees(*last + (1.0 - params.factor) * angle_delta(*last, raw));
+                }
+                Pose::from_axes(axes)
+            }
+        };
+        self.last = Some(smoothed);
+        smoothed
+    }
+}
+
+// the 1€ filter (casiez et al., 2012): a low-pass whose cutoff rises with
+// speed, heavy smoothing when the head is still and little lag when it turns
+#[derive(Default)]
+pub struct OneEuro {
+    axes: Option<[(f64, f64); 3]>, // (value, speed) per axis
+}
+
+// smoothing factor of a first-order low-pass at `cutoff` Hz
+fn alpha(cutoff: f64, dt: f64) -> f64 {
+    let tau = 1.0 / (2.0 * std::f64::consts::PI * cutoff);
+    1.0 / (1.0 + tau / dt)
+}
+
+impl PoseFilter for OneEuro {
+    fn apply(&mut self, pose: Pose, dt: f64, params: &FilterParams) -> Pose {
+        let Some(mut axes) = self.axes else {
+            self.axes = Some(pose.axes().map(|value| (value, 0.0)));
+            return pose;
+        };
+        let dt = dt.max(1e-4);
+        for ((value, speed), raw) in axes.iter_mut().zip(pose.axes()) {
+            let delta = angle_delta(*value, raw);
+            *speed += alpha(params.d_cutoff, dt) * (delta / dt - *speed);
+            let cutoff = params.min_cutoff + params.beta * speed.abs();
+            *value = wrap_degrees(*value + alpha(cutoff, dt) * delta);
+        }
+        self.axes = Some(axes);
+        Pose::from_axes(axes.map(|(value, _)| value))
+    }
+}
+
+// median of the last few packets, drops single-packet spikes
+#[derive(Default)]
+pub struct Median {
+    window: Vec<Pose>,
+    last: Option<Pose>,
+}
+
+impl PoseFilter for Median {
+    fn apply(&mut self, pose: Pose, _dt: f64, params: &FilterParams) -> Pose {
+        self.window.push(pose);
+        let excess = self.window.len().saturating_sub(params.median_window.max(1));
+        self.window.drain(..excess);
+
+        // measured from the last output so the window doesn't straddle the
+        // wrap, and a spike can't become the reference
+        let reference = self.last.unwrap_or(pose).axes();
+        let mut median = [0.0; 3];
+        for axis in 0..3 {
+            let mut offsets: Vec<f64> =
+                self.window.iter().map(|p| angle_delta(reference[axis], p.axes()[axis])).collect();
+            offsets.sort_by(f64::total_cmp);
+            let mid = offsets.len() / 2;
+            let offset = if offsets.len().is_multiple_of(2) { (offsets[mid - 1] + offsets[mid]) / 2.0 } else { offsets[mid] };
+            median[axis] = wrap_degrees(reference[axis] + offset);
+        }
+        let median = Pose::from_axes(median);
+        self.last = Some(median);
+        median
+    }
+}
+
+// ==============================================================================
+// PREDICTION
+// ==============================================================================
+//
+// the filtered pose trails the head by the tracker, filter and audio latency.
+// the predictor pushes it ahead by the head's speed times a lookahead. the
+// speed comes from the raw packets, which stop the moment the head does, so
+// the prediction falls back to the filtered pose instead of running past it.
+// if the packets stop altogether the lead is held for one lookahead and then
+// faded out over the next, rather than kept until the tracker comes back.
+
+// below this speed (degrees/second) it's tracker jitter, not a turn
+const PREDICT_MIN_SPEED: f64 = 10.0;
+
+// low-pass on the speed estimate, Hz
+const PREDICT_SPEED_CUTOFF: f64 = 5.0;
+
+// a gap this long (seconds) means the speed is out of date
+const PREDICT_STALE_SECS: f64 = 0.2;
+
+#[derive(Default)]
+pub struct Predictor {
+    last_raw: Option<Pose>,
+    speed: [f64; 3], // degrees/second, smoothed
+    lead: [f64; 3],  // degrees added to the smoothed pose at the last packet
+}
+
+impl Predictor {
+    // `smoothed` moved ahead by `lookahead` seconds at the raw pose's speed,
+    // by at most `max_degrees` on each axis
+    pub fn predict(&mut self, raw: Pose, smoothed: Pose, dt: f64, lookahead: f64, max_degrees: f64) -> Pose {
+        self.lead = [0.0; 3];
+        let Some(last) = self.last_raw.replace(raw) else { return smoothed };
+        if dt <= 0.0 || dt > PREDICT_STALE_SECS {
+            self.speed = [0.0; 3];
+            return smoothed;
+        }
+
+        for (axis, lead) in self.lead.iter_mut().enumerate() {
+            let measured = angle_delta(last.axes()[axis], raw.axes()[axis]) / dt;
+            let speed = &mut self.speed[axis];
+            *speed += alpha(PREDICT_SPEED_CUTOFF, dt) * (measured - *speed);
+
+            // only as fast as both the smoothed and the latest speed agree
+            // on, so stopping or turning back ends the prediction at once
+            let agreed = if measured * *speed > 0.0 { speed.signum() * speed.abs().min(measured.abs()) } else { 0.0 };
+            if agreed.abs() >= PREDICT_MIN_SPEED {
+                *lead = (agreed * lookahead).clamp(-max_degrees, max_degrees);
+            }
+        }
+        self.fade(smoothed, 0.0, lookahead)
+    }
+
+    // `smoothed` with the lead left `since` seconds after the last packet:
+    // all of it for one lookahead, none of it after two
+    pub fn fade(&mut self, smoothed: Pose, since: f64, lookahead: f64) -> Pose {
+        let left = if lookahead > 0.0 { (2.0 - since / lookahead).clamp(0.0, 1.0) } else { 0.0 };
+        if left == 0.0 {
+            self.lead = [0.0; 3];
+        }
+        let mut axes = smoothed.axes();
+        for (value, lead) in axes.iter_mut().zip(self.lead) {
+            if lead != 0.0 {
+                *value = wrap_degrees(*value + lead * left);
+            }
+        }
+        Pose::from_axes(axes)
+    }
+
+    // whether there's a lead still to fade out
+    pub fn leading(&self) -> bool {
+        self.lead != [0.0; 3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: FilterParams =
+        FilterParams { factor: 0.65, deadzone: 0.5, min_cutoff: 1.0, beta: 0.05, d_cutoff: 1.0, median_window: 3 };
+    const DT: f64 = 0.02;
+
+    fn yaw(yaw: f64) -> Pose {
+        Pose::new(yaw, 0.0, 0.0)
+    }
+
+    #[test]
+    fn ema_crosses_180_the_short_way() {
+        let mut ema = Ema::default();
+        ema.apply(yaw(178.0), DT, &PARAMS);
+        for _ in 0..20 {
+            let smoothed = ema.apply(yaw(-178.0), DT, &PARAMS);
+            // never swings back through the front
+            assert!(smoothed.yaw.abs() > 177.0, "{}", smoothed.yaw);
+        }
+
+        // away from the seam it's the plain exponential average
+        let mut ema = Ema::default();
+        ema.apply(yaw(10.0), DT, &PARAMS);
+        assert!((ema.apply(yaw(20.0), DT, &PARAMS).yaw - 13.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn deadzone_holds_small_moves_and_follows_big_ones() {
+        let mut deadzone = Deadzone::default();
+        deadzone.apply(yaw(10.0), DT, &PARAMS);
+        assert_eq!(deadzone.apply(yaw(10.4), DT, &PARAMS).yaw, 10.0);
+        assert_eq!(deadzone.apply(yaw(9.6), DT, &PARAMS).yaw, 10.0);
+        assert!((deadzone.apply(yaw(12.0), DT, &PARAMS).yaw - 11.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn one_euro_smooths_jitter_more_than_fast_turns() {
+        let mut still = OneEuro::default();
+        still.apply(yaw(0.0), DT, &PARAMS);
+        let jitter = still.apply(yaw(1.0), DT, &PARAMS).yaw;
+
+        let mut turning = OneEuro::default();
+        let mut out = 0.0;
+        for i in 0..=25 {
+            out = turning.apply(yaw(i as f64 * 4.0), DT, &PARAMS).yaw; // 200 deg/s
+        }
+
+        // a 1 degree twitch barely moves it, a fast turn keeps up within a few degrees
+        assert!(jitter < 0.2, "{}", jitter);
+        assert!(100.0 - out < 15.0, "{}", out);
+    }
+
+    #[test]
+    fn median_drops_spikes_across_the_wrap() {
+        let mut median = Median::default();
+        median.apply(yaw(179.0), DT, &PARAMS);
+        median.apply(yaw(-179.0), DT, &PARAMS);
+        let out = median.apply(yaw(0.0), DT, &PARAMS); // one bad packet
+        assert!(out.yaw.abs() > 178.0, "{}", out.yaw);
+    }
+
+    #[test]
+    fn chain_runs_in_order_and_tunables_follow_it() {
+        let kinds = [FilterKind::Median, FilterKind::OneEuro, FilterKind::Ema, FilterKind::Ema];
+        let mut chain = FilterChain::new(&kinds);
+        assert_eq!(chain.apply(yaw(30.0), DT, &PARAMS), yaw(30.0));
+
+        let names: Vec<&str> = tunables(&kinds).iter().map(|(_, p)| p.name()).collect();
+        assert_eq!(names, ["window", "min_cutoff", "beta", "factor"]);
+
+        assert!(FilterChain::new(&[]).apply(yaw(30.0), DT, &PARAMS) == yaw(30.0));
+    }
+
+    #[test]
+    fn prediction_leads_a_turn_and_stops_with_it() {
+        let mut predictor = Predictor::default();
+        let mut predicted = yaw(0.0);
+        // turning left at 100 deg/s, 50 ms ahead
+        for i in 0..=20 {
+            let pose = yaw(i as f64 * 2.0);
+            predicted = predictor.predict(pose, pose, DT, 0.05, 15.0);
+        }
+        assert!((predicted.yaw - 45.0).abs() < 0.5, "{}", predicted.yaw);
+
+        // the head stops: no lead, not even for one packet
+        let stopped = predictor.predict(yaw(40.0), yaw(39.0), DT, 0.05, 15.0);
+        assert_eq!(stopped.yaw, 39.0);
+
+        // a fast turn is capped
+        let mut predictor = Predictor::default();
+        for i in 0..=20 {
+            let pose = yaw(wrap_degrees(i as f64 * 20.0));
+            predicted = predictor.predict(pose, pose, DT, 0.05, 15.0);
+        }
+        assert!((angle_delta(wrap_degrees(400.0), predicted.yaw) - 15.0).abs() < 1e-9, "{}", predicted.yaw);
+    }
+
+    #[test]
+    fn prediction_fades_when_the_tracker_goes_quiet() {
+        let mut predictor = Predictor::default();
+        let mut predicted = yaw(0.0);
+        for i in 0..=20 {
+            let pose = yaw(i as f64 * 2.0);
+            predicted = predictor.predict(pose, pose, DT, 0.05, 15.0);
+        }
+        assert!(predictor.leading());
+
+        // no packet for a while: the lead holds for one lookahead...
+        assert_eq!(predictor.fade(yaw(40.0), 0.04, 0.05), predicted);
+        // ...is halfway gone half a lookahead later...
+        assert!((predictor.fade(yaw(40.0), 0.075, 0.05).yaw - 42.5).abs() < 0.25);
+        // ...and then gone for good
+        assert_eq!(predictor.fade(yaw(40.0), 0.1, 0.05), yaw(40.0));
+        assert!(!predictor.leading());
+        assert_eq!(predictor.fade(yaw(40.0), 0.0, 0.05), yaw(40.0));
+    }
+
+    #[test]
+    fn prediction_ignores_jitter() {
+        let mut predictor = Predictor::default();
+        for i in 0..20 {
+            let pose = yaw(if i % 2 == 0 { 0.1 } else { -0.1 });
+            assert_eq!(predictor.predict(pose, yaw(0.0), DT, 0.05, 15.0), yaw(0.0));
+        }
+    }
+
+    #[test]
+    fn params_step_within_their_range() {
+        let mut params = PARAMS;
+        Param::Factor.adjust(&mut params, true);
+        assert!((params.factor - 0.7).abs() < 1e-9);
+        for _ in 0..10 {
+            Param::Factor.adjust(&mut params, true);
+        }
+        assert_eq!(params.factor, 0.99);
+        for _ in 0..5 {
+            Param::MedianWindow.adjust(&mut params, false);
+        }
+        assert_eq!(params.median_window, 1);
+    }
+}
@@ -0,0 +1,430 @@
+// ==============================================================================
+// FILTER-CHAIN GRAPH
+// ==============================================================================
+//
+// single source of truth for the filter-chain: node names used by the param
+// pushes, and the conf generator that builds the graph from them.
+
+use std::fmt::Write;
+
+// filter-chain node names
+pub const SPAT_LEFT: &str = "spat_left";
+pub const SPAT_RIGHT: &str = "spat_right";
+pub const MIX_LEFT: &str = "mix_l";
+pub const MIX_RIGHT: &str = "mix_r";
+pub const CONV_LEFT: &str = "conv_l";
+pub const CONV_RIGHT: &str = "conv_r";
+pub const FINAL_MIX_LEFT: &str = "final_mix_l";
+pub const FINAL_MIX_RIGHT: &str = "final_mix_r";
+
+pub const LFE: &str = "lfe";
+
+// a sink channel, the node that renders it and where the channel sits when a
+// layout doesn't place it (ITU-R BS.775 / BS.2051, +azimuth = left)
+pub struct Channel {
+    pub name: &'static str,
+    pub node: &'static str,
+    pub label: &'static str,
+    pub azimuth: f64,
+    pub elevation: f64,
+}
+
+impl Channel {
+    // LFE has no direction, it goes to both ears unspatialized
+    pub fn is_lfe(&self) -> bool {
+        self.node == LFE
+    }
+}
+
+const fn ch(name: &'static str, node: &'static str, label: &'static str, azimuth: f64) -> Channel {
+    Channel { name, node, label, azimuth, elevation: 0.0 }
+}
+
+// height speakers sit 45 degrees up
+const fn top(name: &'static str, node: &'static str, label: &'static str, azimuth: f64) -> Channel {
+    Channel { name, node, label, azimuth, elevation: 45.0 }
+}
+
+const FRONT_LEFT: Channel = ch("FL", SPAT_LEFT, "Left", 30.0);
+const FRONT_RIGHT: Channel = ch("FR", SPAT_RIGHT, "Right", -30.0);
+const CENTER: Channel = ch("FC", "spat_center", "Center", 0.0);
+const LOW_FREQ: Channel = ch("LFE", LFE, "LFE", 0.0);
+
+// channels of each sink format, in audio.position order
+pub const STEREO: &[Channel] = &[FRONT_LEFT, FRONT_RIGHT];
+pub const SURROUND_51: &[Channel] = &[
+    FRONT_LEFT,
+    FRONT_RIGHT,
+    CENTER,
+    LOW_FREQ,
+    ch("RL", "spat_rear_left", "Surround Left", 110.0),
+    ch("RR", "spat_rear_right", "Surround Right", -110.0),
+];
+pub const SURROUND_71: &[Channel] = &[
+    FRONT_LEFT,
+    FRONT_RIGHT,
+    CENTER,
+    LOW_FREQ,
+    ch("RL", "spat_rear_left", "Rear Left", 135.0),
+    ch("RR", "spat_rear_right", "Rear Right", -135.0),
+    ch("SL", "spat_side_left", "Side Left", 90.0),
+    ch("SR", "spat_side_right", "Side Right", -90.0),
+];
+
+// 5.1 plus a pair of top-middle speakers (Dolby 5.1.2)
+pub const SURROUND_512: &[Channel] = &[
+    FRONT_LEFT,
+    FRONT_RIGHT,
+    CENTER,
+    LOW_FREQ,
+    ch("RL", "spat_rear_left", "Surround Left", 110.0),
+    ch("RR", "spat_rear_right", "Surround Right", -110.0),
+    top("TSL", "spat_top_left", "Top Left", 90.0),
+    top("TSR", "spat_top_right", "Top Right", -90.0),
+];
+// 7.1 plus front and rear height pairs
+pub const SURROUND_714: &[Channel] = &[
+    FRONT_LEFT,
+    FRONT_RIGHT,
+    CENTER,
+    LOW_FREQ,
+    ch("RL", "spat_rear_left", "Rear Left", 135.0),
+    ch("RR", "spat_rear_right", "Rear Right", -135.0),
+    ch("SL", "spat_side_left", "Side Left", 90.0),
+    ch("SR", "spat_side_right", "Side Right", -90.0),
+    top("TFL", "spat_top_front_left", "Top Front Left", 45.0),
+    top("TFR", "spat_top_front_right", "Top Front Right", -45.0),
+    top("TRL", "spat_top_rear_left", "Top Rear Left", 135.0),
+    top("TRR", "spat_top_rear_right", "Top Rear Right", -135.0),
+];
+
+pub struct Format {
+    pub name: &'static str, // as written in the config
+    pub channels: &'static [Channel],
+}
+
+pub const FORMATS: &[Format] = &[
+    Format { name: "stereo", channels: STEREO },
+    Format { name: "5.1", channels: SURROUND_51 },
+    Format { name: "7.1", channels: SURROUND_71 },
+    Format { name: "5.1.2", channels: SURROUND_512 },
+    Format { name: "7.1.4", channels: SURROUND_714 },
+];
+
+pub fn format(name: &str) -> Option<&'static Format> {
+    FORMATS.iter().find(|f| f.name == name)
+}
+
+// every channel a layout can place, across all formats
+pub fn speaker_channels() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = Vec::new();
+    for channel in FORMATS.iter().flat_map(|f| f.channels) {
+        if !channel.is_lfe() && !names.contains(&channel.name) {
+            names.push(channel.name);
+        }
+    }
+    names
+}
+
+// first line of every conf we write; install only overwrites files starting with it
+pub const GENERATED_HEADER: &str = "# generated by 'spatial-track generate-conf'";
+
+// builtin mixers sum at most this many inputs
+const MIXER_INPUTS: usize = 8;
+
+// where the installed assets live
+pub const DEFAULT_SOFA_PATH: &str = "/usr/share/pipewire/sofa/subject_021.sofa";
+pub const DEFAULT_IR_PATH: &str = "/usr/share/pipewire/convolver/reverb.wav";
+
+// "node:control" name as the filter-chain expects it in Props
+pub fn control(node: &str, control: &str) -> String {
+    format!("{}:{}", node, control)
+}
+
+// effect_input.spatializer -> effect_output.spatializer
+pub fn playback_node_name(sink_name: &str) -> String {
+    match sink_name.strip_prefix("effect_input.") {
+        Some(rest) => format!("effect_output.{}", rest),
+        None => format!("{}.output", sink_name),
+    }
+}
+
+// a virtual speaker and the starting values of its sofa controls
+pub struct SpeakerSpec {
+    pub node: &'static str,
+    pub label: &'static str,
+    pub heard: f64,   // azimuth as heard, + = left; only for the comment
+    pub azimuth: f64, // sofa convention, what the control is set to
+    pub elevation: f64,
+    pub radius: f64,
+    pub gain: f64, // level on top of speaker_mix_gain, 1.0 = as is
+}
+
+pub struct GraphSpec<'a> {
+    pub node_name: &'a str,
+    pub format: &'static Format,
+    pub sofa_path: &'a str,
+    pub ir_path: &'a str,
+    pub speakers: Vec<SpeakerSpec>, // one per non-LFE channel, in channel order
+    pub lfe_gain: f64,
+    pub dry_gain: f64,
+    pub wet_gain: f64,
+}
+
+// where each summed source lands: builtin mixers only take 8 inputs, so more
+// sources chain mixers, each stage feeding the next through its In 1. the
+// last stage carries the base name so everything downstream stays the same.
+struct MixerChain {
+    stages: Vec<Vec<f64>>,     // input gains per stage
+    slots: Vec<(usize, usize)>, // per source: (stage, input number)
+}
+
+impl MixerChain {
+    fn new(gains: &[f64]) -> Self {
+        let mut chain = MixerChain { stages: vec![Vec::new()], slots: Vec::new() };
+        for &gain in gains {
+            if chain.stages.last().map_or(0, |s| s.len()) == MIXER_INPUTS {
+                chain.stages.push(vec![1.0]); // previous stage
+            }
+            let stage = chain.stages.len() - 1;
+            chain.stages[stage].push(gain);
+            chain.slots.push((stage, chain.stages[stage].len()));
+        }
+        chain
+    }
+
+    fn stage_name(&self, base: &str, stage: usize) -> String {
+        if stage + 1 == self.stages.len() {
+            base.to_string()
+        } else {
+            format!("{}_{}", base, stage + 1)
+        }
+    }
+}
+
+// every speaker goes into both ear mixers at the same level, LFE after them;
+// sqrt(1 / 2n) keeps the loudness of the stereo graph (0.5 each)
+pub fn speaker_mix_gain(speakers: usize) -> f64 {
+    (0.5 / speakers.max(1) as f64).sqrt()
+}
+
+// the sofa nodes have no gain of their own, so a speaker's level is the
+// "Gain N" of its input on the left and right ear mixers. one pair per
+// non-LFE channel, in channel order
+pub fn speaker_gain_controls(channels: &[Channel]) -> Vec<[String; 2]> {
+    // only the number of mixer inputs decides where each one lands, and the
+    // speakers come first like in generate_conf
+    let speakers = channels.iter().filter(|channel| !channel.is_lfe()).count();
+    let chain = MixerChain::new(&vec![0.0; channels.len()]);
+    chain.slots[..speakers]
+        .iter()
+        .map(|&(stage, input)| {
+            let gain = format!("Gain {}", input);
+            [control(&chain.stage_name(MIX_LEFT, stage), &gain), control(&chain.stage_name(MIX_RIGHT, stage), &gain)]
+        })
+        .collect()
+}
+
+// render the filter-chain module config for ~/.config/pipewire/pipewire.conf.d/
+pub fn generate_conf(spec: &GraphSpec) -> String {
+    let mut out = String::new();
+    let w = &mut out;
+    let channels = spec.format.channels;
+    let has_lfe = channels.iter().any(|c| c.is_lfe());
+    let description = match spec.format.name {
+        "stereo" => "Spatializer Stereo".to_string(),
+        name => format!("Spatializer {}", name),
+    };
+
+    let speaker_gain = speaker_mix_gain(spec.speakers.len());
+    let mut sources: Vec<f64> = spec.speakers.iter().map(|speaker| speaker_gain * speaker.gain).collect();
+    if has_lfe {
+        sources.push(spec.lfe_gain);
+    }
+    let chain = MixerChain::new(&sources);
+
+    writeln!(w, "{}, do not edit by hand", GENERATED_HEADER).ok();
+    writeln!(w, "context.modules = [").ok();
+    writeln!(w, "    {{   name = libpipewire-module-filter-chain").ok();
+    writeln!(w, "        args = {{").ok();
+    writeln!(w, "            node.description = \"{}\"", description).ok();
+    writeln!(w, "            media.name       = \"{}\"", description).ok();
+    writeln!(w, "            filter.graph = {{").ok();
+    writeln!(w, "                nodes = [").ok();
+
+    for speaker in &spec.speakers {
+        writeln!(
+            w,
+            "                    # --- {} Virtual Speaker (heard at {:+.0} deg) ---",
+            speaker.label,
+            no_negative_zero(speaker.heard)
+        )
+        .ok();
+        writeln!(w, "                    {{").ok();
+        writeln!(w, "                        type   = sofa").ok();
+        writeln!(w, "                        label  = spatializer").ok();
+        writeln!(w, "                        name   = {}", speaker.node).ok();
+        writeln!(w, "                        config = {{ filename = \"{}\" }}", spec.sofa_path).ok();
+        writeln!(
+            w,
+            "                        control = {{ \"Azimuth\" = {:.1} \"Elevation\" = {:.1} \"Radius\" = {:.1} }}",
+            no_negative_zero(speaker.azimuth),
+            no_negative_zero(speaker.elevation),
+            no_negative_zero(speaker.radius)
+        )
+        .ok();
+        writeln!(w, "                    }}").ok();
+    }
+
+    if has_lfe {
+        writeln!(w, "                    # --- LFE (not spatialized, mixed into both ears) ---").ok();
+        write_node(w, "copy", LFE, None, None);
+    }
+
+    for (label, node) in [("Left", MIX_LEFT), ("Right", MIX_RIGHT)] {
+        writeln!(w, "                    # --- {} Channel Mixer ---", label).ok();
+        for (stage, gains) in chain.stages.iter().enumerate() {
+            write_node(w, "mixer", &chain.stage_name(node, stage), None, Some(gains));
+        }
+    }
+
+    for (label, node, channel) in [("Left", CONV_LEFT, 0), ("Right", CONV_RIGHT, 1)] {
+        writeln!(w, "                    # --- Convolver Reverb {} ---", label).ok();
+        write_node(w, "convolver", node, Some((spec.ir_path, channel)), None);
+    }
+
+    writeln!(w, "                    # --- Final Mixer (Dry/Wet blend) ---").ok();
+    for node in [FINAL_MIX_LEFT, FINAL_MIX_RIGHT] {
+        write_node(w, "mixer", node, None, Some(&[spec.dry_gain, spec.wet_gain]));
+    }
+
+    writeln!(w, "                ]").ok();
+    writeln!(w).ok();
+    let inputs: Vec<String> = channels.iter().map(|c| format!("\"{}:In\"", c.node)).collect();
+    writeln!(w, "                inputs  = [ {} ]", inputs.join(" ")).ok();
+    writeln!(w).ok();
+    writeln!(w, "                links = [").ok();
+    writeln!(w, "                    # Spatializer -> First Mixer").ok();
+    let mut from_nodes: Vec<(&str, &str, &str)> = spec.speakers.iter().map(|s| (s.node, "Out L", "Out R")).collect();
+    if has_lfe {
+        from_nodes.push((LFE, "Out", "Out"));
+    }
+    for ((from, left_port, right_port), &(stage, input)) in from_nodes.into_iter().zip(&chain.slots) {
+        let in_port = format!("In {}", input);
+        write_link(w, from, left_port, &chain.stage_name(MIX_LEFT, stage), &in_port, None);
+        write_link(w, from, right_port, &chain.stage_name(MIX_RIGHT, stage), &in_port, None);
+    }
+    for stage in 1..chain.stages.len() {
+        for node in [MIX_LEFT, MIX_RIGHT] {
+            write_link(w, &chain.stage_name(node, stage - 1), "Out", &chain.stage_name(node, stage), "In 1", Some("chained mixer"));
+        }
+    }
+    writeln!(w).ok();
+    writeln!(w, "                    # Mixer -> Convolver (for reverb)").ok();
+    write_link(w, MIX_LEFT, "Out", CONV_LEFT, "In", None);
+    write_link(w, MIX_RIGHT, "Out", CONV_RIGHT, "In", None);
+    writeln!(w).ok();
+    writeln!(w, "                    # Dry + Wet Mix (parallel routing)").ok();
+    write_link(w, MIX_LEFT, "Out", FINAL_MIX_LEFT, "In 1", Some("Dry left"));
+    write_link(w, CONV_LEFT, "Out", FINAL_MIX_LEFT, "In 2", Some("Wet left"));
+    write_link(w, MIX_RIGHT, "Out", FINAL_MIX_RIGHT, "In 1", Some("Dry right"));
+    write_link(w, CONV_RIGHT, "Out", FINAL_MIX_RIGHT, "In 2", Some("Wet right"));
+    writeln!(w, "                ]").ok();
+    writeln!(w).ok();
+    writeln!(w, "                outputs = [ \"{}:Out\" \"{}:Out\" ]", FINAL_MIX_LEFT, FINAL_MIX_RIGHT).ok();
+    writeln!(w, "            }}").ok();
+    let positions: Vec<&str> = channels.iter().map(|c| c.name).collect();
+    writeln!(w, "            capture.props = {{").ok();
+    writeln!(w, "                node.name      = \"{}\"", spec.node_name).ok();
+    writeln!(w, "                media.class    = \"Audio/Sink\"").ok();
+    writeln!(w, "                audio.channels = {}", channels.len()).ok();
+    writeln!(w, "                audio.position = [ {} ]", positions.join(" ")).ok();
+    writeln!(w, "            }}").ok();
+    writeln!(w, "            playback.props = {{").ok();
+    writeln!(w, "                node.name      = \"{}\"", playback_node_name(spec.node_name)).ok();
+    writeln!(w, "                node.passive   = true").ok();
+    writeln!(w, "                audio.channels = 2").ok();
+    writeln!(w, "                audio.position = [ FL FR ]").ok();
+    writeln!(w, "            }}").ok();
+    writeln!(w, "        }}").ok();
+    writeln!(w, "    }}").ok();
+    writeln!(w, "]").ok();
+
+    out
+}
+
+// a builtin node, with an optional convolver (file, channel) config
+// and optional mixer (Gain 1, Gain 2, ...) controls
+fn write_node(w: &mut String, label: &str, name: &str, convolver: Option<(&str, u32)>, gains: Option<&[f64]>) {
+    writeln!(w, "                    {{").ok();
+    writeln!(w, "                        type   = builtin").ok();
+    writeln!(w, "                        label  = {}", label).ok();
+    writeln!(w, "                        name   = {}", name).ok();
+    if let Some((filename, channel)) = convolver {
+        writeln!(w, "                        config = {{").ok();
+        writeln!(w, "                            filename = \"{}\"", filename).ok();
+        writeln!(w, "                            channel = {}", channel).ok();
+        writeln!(w, "                        }}").ok();
+    }
+    if let Some(gains) = gains {
+        let controls: Vec<String> = gains.iter().enumerate().map(|(i, g)| format!("\"Gain {}\" = {:.2}", i + 1, g)).collect();
+        writeln!(w, "                        control = {{ {} }}", controls.join(" ")).ok();
+    }
+    writeln!(w, "                    }}").ok();
+}
+
+fn write_link(w: &mut String, from: &str, out_port: &str, to: &str, in_port: &str, comment: Option<&str>) {
+    let output = format!("\"{}:{}\"", from, out_port);
+    let input = format!("\"{}:{}\"", to, in_port);
+    match comment {
+        Some(comment) => writeln!(w, "                    {{ output = {:<14} input = {} }}  # {}", output, input, comment),
+        None => writeln!(w, "                    {{ output = {:<14} input = {} }}", output, input),
+    }
+    .ok();
+}
+
+// -pitch at rest is -0.0 and the head rotation leaves tiny negative
+// rounding errors, either would print as "-0.0"
+fn no_negative_zero(value: f64) -> f64 {
+    if value.abs() < 0.05 {
+        0.0
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixer_chain_spills_past_eight_inputs() {
+        let chain = MixerChain::new(&[0.5; 12]);
+        assert_eq!(chain.stages.len(), 2);
+        assert_eq!(chain.stages[0].len(), 8);
+        // In 1 of the second stage is the first stage's output
+        assert_eq!(chain.stages[1], vec![1.0, 0.5, 0.5, 0.5, 0.5]);
+        assert_eq!(chain.slots[7], (0, 8));
+        assert_eq!(chain.slots[8], (1, 2));
+        assert_eq!(chain.stage_name(MIX_LEFT, 0), "mix_l_1");
+        assert_eq!(chain.stage_name(MIX_LEFT, 1), MIX_LEFT);
+
+        let stereo = MixerChain::new(&[0.5; 2]);
+        assert_eq!(stereo.stage_name(MIX_LEFT, 0), MIX_LEFT);
+    }
+
+    #[test]
+    fn speaker_gains_follow_the_mixer_chain() {
+        let stereo = speaker_gain_controls(STEREO);
+        assert_eq!(stereo[1], ["mix_l:Gain 2".to_string(), "mix_r:Gain 2".to_string()]);
+
+        // 11 speakers and LFE: the first eight land on the first stage, LFE
+        // after the rest on the second
+        let controls = speaker_gain_controls(SURROUND_714);
+        assert_eq!(controls.len(), 11);
+        assert_eq!(controls[0][0], "mix_l_1:Gain 1");
+        assert_eq!(controls[7][1], "mix_r_1:Gain 8");
+        assert_eq!(controls[8][0], "mix_l:Gain 2");
+        assert_eq!(controls[10][1], "mix_r:Gain 4");
+    }
+}
@@ -0,0 +1,382 @@
+// ==============================================================================
+// INSTALL / UNINSTALL / DOCTOR
+// ==============================================================================
+//
+// replaces the manual README steps: everything goes under the user's XDG
+// dirs (see paths.rs), and doctor checks each prerequisite in turn.
+
+use std::fs;
+use std::io::Read;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::{graph, packet, paths, pwdump};
+
+// the HRTF set from assets/, bundled so install works from any directory
+const BUNDLED_SOFA: &[u8] = include_bytes!("../assets/subject_021.sofa");
+
+// first bytes of every HDF5 (and so SOFA) file
+const HDF5_SIGNATURE: &[u8] = b"\x89HDF\r\n\x1a\n";
+
+// how long doctor listens for opentrack packets
+const PACKET_WAIT: Duration = Duration::from_secs(3);
+
+pub fn install(conf: &str, sofa_source: Option<&str>, ir_source: Option<&str>, restart: bool) -> Result<(), String> {
+    let data_dir = paths::data_dir();
+    fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create {}: {}", data_dir.display(), e))?;
+
+    let sofa = paths::installed_sofa();
+    match sofa_source {
+        Some(source) => copy_file(Path::new(source), &sofa)?,
+        None => write_file(&sofa, BUNDLED_SOFA)?,
+    }
+    println!("  ✓ SOFA      {}", sofa.display());
+
+    let ir = paths::installed_ir();
+    match ir_source {
+        Some(source) => copy_file(Path::new(source), &ir)?,
+        None => write_file(&ir, &synth_reverb_wav())?,
+    }
+    println!("  ✓ Reverb IR {}", ir.display());
+
+    let conf_path = paths::pipewire_conf();
+    if let Some(dir) = conf_path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    if let Some(backup) = write_conf(&conf_path, conf)? {
+        println!("  ! Kept your own config as {}", backup.display());
+    }
+    println!("  ✓ Config    {}", conf_path.display());
+
+    if restart {
+        restart_pipewire()?;
+    }
+    Ok(())
+}
+
+pub fn uninstall(restart: bool) -> Result<(), String> {
+    let conf_path = paths::pipewire_conf();
+    if conf_path.exists() {
+        fs::remove_file(&conf_path).map_err(|e| format!("Failed to remove {}: {}", conf_path.display(), e))?;
+        println!("  ✓ Removed {}", conf_path.display());
+    }
+
+    let data_dir = paths::data_dir();
+    if data_dir.exists() {
+        fs::remove_dir_all(&data_dir).map_err(|e| format!("Failed to remove {}: {}", data_dir.display(), e))?;
+        println!("  ✓ Removed {}", data_dir.display());
+    }
+
+    if restart {
+        restart_pipewire()?;
+    }
+    Ok(())
+}
+
+fn restart_pipewire() -> Result<(), String> {
+    let status = Command::new("systemctl")
+        .args(["--user", "restart", "pipewire", "pipewire-pulse"])
+        .status()
+        .map_err(|e| format!("Failed to run systemctl: {}", e))?;
+    if !status.success() {
+        return Err("Failed to restart pipewire (systemctl --user restart pipewire pipewire-pulse)".to_string());
+    }
+    println!("  ✓ Restarted pipewire");
+    Ok(())
+}
+
+fn copy_file(from: &Path, to: &Path) -> Result<(), String> {
+    fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to copy {} to {}: {}", from.display(), to.display(), e))
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    fs::write(path, bytes).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// a conf we didn't generate is someone's hand-edited filter chain: move it
+// to .bak instead of overwriting it, and return where it went
+fn write_conf(path: &Path, conf: &str) -> Result<Option<PathBuf>, String> {
+    let mut backup = None;
+    if let Ok(existing) = fs::read_to_string(path) {
+        if !existing.starts_with(graph::GENERATED_HEADER) {
+            let bak = path.with_extension("conf.bak");
+            fs::rename(path, &bak).map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+            backup = Some(bak);
+        }
+    }
+    write_file(path, conf.as_bytes())?;
+    Ok(backup)
+}
+
+// ==============================================================================
+// synthetic reverb
+// ==============================================================================
+
+// a small-room impulse response: decorrelated noise per channel with an
+// exponential decay and a gentle low-pass, normalized to unit energy so the
+// wet signal sits at roughly the same level as the dry one
+fn synth_reverb_wav() -> Vec<u8> {
+    const RATE: u32 = 48000;
+    const LENGTH_SECS: f64 = 1.0;
+    const RT60_SECS: f64 = 0.6;
+    const PRE_DELAY_SECS: f64 = 0.012;
+
+    let frames = (RATE as f64 * LENGTH_SECS) as usize;
+    let pre_delay = (RATE as f64 * PRE_DELAY_SECS) as usize;
+
+    let mut channels = Vec::new();
+    for seed in [0x1234_5678u32, 0x9abc_def1u32] {
+        let mut state = seed;
+        let mut lowpass = 0.0;
+        let mut samples: Vec<f64> = (0..frames)
+            .map(|i| {
+                if i < pre_delay {
+                    return 0.0;
+                }
+                // xorshift32 noise in -1..1
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                let noise = state as f64 / u32::MAX as f64 * 2.0 - 1.0;
+
+                // -60 dB after RT60_SECS
+                let t = (i - pre_delay) as f64 / RATE as f64;
+                let envelope = (-6.9 * t / RT60_SECS).exp();
+                lowpass += 0.4 * (noise * envelope - lowpass);
+                lowpass
+            })
+            .collect();
+
+        let energy: f64 = samples.iter().map(|s| s * s).sum();
+        let scale = 1.0 / energy.sqrt().max(f64::EPSILON);
+        samples.iter_mut().for_each(|s| *s *= scale);
+        channels.push(samples);
+    }
+
+    // 16-bit stereo pcm wav
+    let data_len = (frames * 2 * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // pcm
+    wav.extend_from_slice(&2u16.to_le_bytes()); // channels
+    wav.extend_from_slice(&RATE.to_le_bytes());
+    wav.extend_from_slice(&(RATE * 4).to_le_bytes()); // byte rate
+    wav.extend_from_slice(&4u16.to_le_bytes()); // block align
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for i in 0..frames {
+        for channel in &channels {
+            let sample = (channel[i].clamp(-1.0, 1.0) * i16::MAX as f64) as i16;
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+    wav
+}
+
+// ==============================================================================
+// doctor
+// ==============================================================================
+
+struct Report {
+    failures: u32,
+}
+
+impl Report {
+    fn pass(&self, check: &str, detail: String) {
+        println!("  \x1B[1;32m[PASS]\x1B[0m {:<22} {}", check, detail);
+    }
+
+    fn fail(&mut self, check: &str, detail: String, hint: &str) {
+        self.failures += 1;
+        println!("  \x1B[1;31m[FAIL]\x1B[0m {:<22} {}", check, detail);
+        println!("         \x1B[90m→ {}\x1B[0m", hint);
+    }
+
+    fn skip(&self, check: &str, detail: String) {
+        println!("  \x1B[1;33m[SKIP]\x1B[0m {:<22} {}", check, detail);
+    }
+}
+
+// runs every check and prints a report, returns whether all of them passed
+pub fn doctor(node_name: &str, listen_addr: SocketAddr) -> bool {
+    let mut report = Report { failures: 0 };
+    println!("spatial-track doctor");
+    println!();
+
+    // 1. filter-chain conf
+    let conf_path = paths::pipewire_conf();
+    let conf = fs::read_to_string(&conf_path).ok();
+    match &conf {
+        Some(_) => report.pass("PipeWire config", conf_path.display().to_string()),
+        None => report.fail(
+            "PipeWire config",
+            format!("{} not found", conf_path.display()),
+            "run 'spatial-track install'",
+        ),
+    }
+
+    // the asset paths the conf points at, or where install would put them
+    let (sofa_path, ir_path) = conf
+        .as_deref()
+        .map(conf_asset_paths)
+        .unwrap_or((None, None));
+    let sofa_path = sofa_path.unwrap_or_else(paths::installed_sofa);
+    let ir_path = ir_path.unwrap_or_else(paths::installed_ir);
+
+    // 2. sofa file
+    match read_header(&sofa_path, HDF5_SIGNATURE.len()) {
+        Ok(header) if header == HDF5_SIGNATURE => report.pass("SOFA file", sofa_path.display().to_string()),
+        Ok(_) => report.fail(
+            "SOFA file",
+            format!("{} is not a SOFA (HDF5) file", sofa_path.display()),
+            "reinstall with 'spatial-track install' or pass --sofa",
+        ),
+        Err(e) => report.fail("SOFA file", e, "run 'spatial-track install'"),
+    }
+
+    // 3. reverb impulse response
+    match read_header(&ir_path, 12) {
+        Ok(header) if header.starts_with(b"RIFF") && &header[8..12] == b"WAVE" => {
+            report.pass("Reverb IR", ir_path.display().to_string())
+        }
+        Ok(_) => report.fail(
+            "Reverb IR",
+            format!("{} is not a WAV file", ir_path.display()),
+            "reinstall with 'spatial-track install' or pass --ir",
+        ),
+        Err(e) => report.fail("Reverb IR", e, "run 'spatial-track install'"),
+    }
+
+    // 4. filter-chain node
+    match pwdump::discover(node_name) {
+        Ok(Some(node)) => report.pass("Spatializer node", format!("'{}' is node {}", node_name, node.id)),
+        Ok(None) => report.fail(
+            "Spatializer node",
+            format!("'{}' not found", node_name),
+            "restart pipewire: systemctl --user restart pipewire pipewire-pulse",
+        ),
+        Err(e) => report.fail("Spatializer node", e, "is pipewire running and pw-dump installed?"),
+    }
+
+    // 5 + 6. udp port, then listen on it for opentrack
+    match UdpSocket::bind(listen_addr) {
+        Ok(socket) => {
+            report.pass("UDP port", format!("{} is free", listen_addr));
+            socket.set_read_timeout(Some(PACKET_WAIT)).ok();
+            let mut buf = [0u8; 64];
+            match socket.recv_from(&mut buf) {
+                Ok((len, from)) => match packet::parse(&buf[..len]) {
+                    Ok(_) => report.pass("OpenTrack packets", format!("received from {}", from)),
+                    Err(e) => report.fail(
+                        "OpenTrack packets",
+                        format!("{} from {}", e, from),
+                        "set OpenTrack's output to 'UDP over network'",
+                    ),
+                },
+                Err(_) => report.fail(
+                    "OpenTrack packets",
+                    format!("nothing in {}s", PACKET_WAIT.as_secs()),
+                    &format!("start OpenTrack with 'UDP over network' output to {}", listen_addr),
+                ),
+            }
+        }
+        Err(e) => {
+            report.fail(
+                "UDP port",
+                format!("{}: {}", listen_addr, e),
+                "is spatial-track (or another tracker client) already running?",
+            );
+            report.skip("OpenTrack packets", "port not available".to_string());
+        }
+    }
+
+    println!();
+    if report.failures == 0 {
+        println!("All checks passed.");
+    } else {
+        println!("{} check(s) failed.", report.failures);
+    }
+    report.failures == 0
+}
+
+// (sofa, ir) filenames referenced by a filter-chain conf
+fn conf_asset_paths(conf: &str) -> (Option<PathBuf>, Option<PathBuf>) {
+    let mut sofa = None;
+    let mut ir = None;
+    for line in conf.lines() {
+        let Some((_, rest)) = line.split_once("filename") else { continue };
+        let Some(value) = rest.split('"').nth(1) else { continue };
+        if value.ends_with(".sofa") {
+            sofa.get_or_insert_with(|| PathBuf::from(value));
+        } else {
+            ir.get_or_insert_with(|| PathBuf::from(value));
+        }
+    }
+    (sofa, ir)
+}
+
+fn read_header(path: &Path, len: usize) -> Result<Vec<u8>, String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut header = vec![0u8; len];
+    file.read_exact(&mut header)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(header)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_asset_paths_in_filter_chain_conf() {
+        let conf = r#"
+            config = { filename = "/home/me/.local/share/spatial-track/subject_021.sofa" }
+            config = {
+                filename = "/home/me/.local/share/spatial-track/reverb.wav"
+                channel = 0
+            }
+        "#;
+        let (sofa, ir) = conf_asset_paths(conf);
+        assert_eq!(sofa, Some(PathBuf::from("/home/me/.local/share/spatial-track/subject_021.sofa")));
+        assert_eq!(ir, Some(PathBuf::from("/home/me/.local/share/spatial-track/reverb.wav")));
+    }
+
+    #[test]
+    fn hand_written_conf_is_backed_up_not_overwritten() {
+        let dir = std::env::temp_dir().join(format!("spatial-track-install-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("99-spatializer.conf");
+        let generated = format!("{}, do not edit by hand\ncontext.modules = []\n", graph::GENERATED_HEADER);
+
+        // nothing there, or one of ours: just written
+        assert_eq!(write_conf(&path, &generated), Ok(None));
+        assert_eq!(write_conf(&path, &generated), Ok(None));
+
+        fs::write(&path, "# my tweaked filter chain\n").unwrap();
+        let backup = write_conf(&path, &generated).unwrap();
+        assert_eq!(backup, Some(dir.join("99-spatializer.conf.bak")));
+        assert_eq!(fs::read_to_string(dir.join("99-spatializer.conf.bak")).unwrap(), "# my tweaked filter chain\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), generated);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn synthetic_reverb_is_a_stereo_wav() {
+        let wav = synth_reverb_wav();
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+        assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 2);
+        assert_eq!(wav.len(), 44 + u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]) as usize);
+    }
+}
@@ -1,6 +1,7 @@
 use std::io::{stdout, Write};
-use std::net::UdpSocket;
-use std::process::{Command, Stdio};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
 use std::time::{Duration, Instant};
 
 use crossterm::{
@@ -11,126 +12,286 @@ use crossterm::{
 };
 
 
-// smoothing: higher = smoother but more latency (0.0 - 0.99)
-const SMOOTHING_FACTOR: f64 = 0.65;
+// tuning values (smoothing, radius/width limits, speaker angles, node name)
+// come from the config file, see config.rs
 
-// min time between updates (20ms = ~50fps)
-const UPDATE_RATE_MS: u64 = 20;
+// how often to look for the node (or check it's still there once linked)
+const NODE_CHECK_SECS: u64 = 2;
 
-// only send command if angle changes by this many degrees
-const CHANGE_THRESHOLD: f64 = 0.5;
+// how often to let a backend with queued work make progress
+const BACKEND_POLL_MS: u64 = 5;
 
-// default radius, can change at runtime
-const DEFAULT_RADIUS: f64 = 1.5;
-const MIN_RADIUS: f64 = 0.1;
-const MAX_RADIUS: f64 = 10.0;
-const RADIUS_STEP: f64 = 0.1;
+// redraw this often when nothing else does, so the time since the last
+// packet keeps counting
+const IDLE_RENDER_MS: u64 = 250;
 
-// dynamic reverb wet/dry mix depending on distance
-const MIN_REVERB: f64 = 0.05;  // closest
-const MAX_REVERB: f64 = 0.60;  // farthest
+// no packet for this long and the tracker counts as gone
+const TRACKER_TIMEOUT: Duration = Duration::from_secs(1);
 
-// speaker angles for front and back modes (base angles at 100% width)
-const FRONT_LEFT_ANGLE: f64 = 45.0;   // +45° (front-left) - wider for less focus
-const FRONT_RIGHT_ANGLE: f64 = -45.0; // -45° (front-right)
-const BACK_LEFT_ANGLE: f64 = 135.0;   // +135° (back-left)
-const BACK_RIGHT_ANGLE: f64 = -135.0; // -135° (back-right)
+// drop the link and search again after this many failed pushes in a row
+const MAX_PUSH_FAILURES: u32 = 3;
 
-// stereo width control: adjusts speaker separation
-const DEFAULT_WIDTH: f64 = 1.0;  // 100% = full separation
-const MIN_WIDTH: f64 = 0.3;      // 30% = narrow (more focused)
-const MAX_WIDTH: f64 = 1.5;      // 150% = extra wide (very diffuse)
-const WIDTH_STEP: f64 = 0.1;
-
-// node name to search for in pipewire
-const SPATIALIZER_NODE_NAME: &str = "effect_input.spatializer";
+// push when the listener moved this far (metres), like change_threshold for angles
+const POSITION_CHANGE_M: f64 = 0.005;
 
 // ==============================================================================
 // DATA STRUCTURES
 // ==============================================================================
 
-#[derive(Clone, Copy, PartialEq)]
-enum SpeakerMode {
-    Front,
-    Back,
+// everything the keyboard can change at runtime, saved on quit (see state.rs)
+#[derive(Clone, Copy)]
+struct Settings {
+    radius: f64,
+    width: f64,
+    layout: usize, // index into config.layouts
+    reverb_enabled: bool,
+    filter: filter::FilterParams,
+    preset: Option<usize>, // last recalled preset slot
+    tuning: usize,         // which filter parameter [ and ] change, not saved
 }
 
-impl SpeakerMode {
-    fn label(&self) -> &'static str {
-        match self {
-            SpeakerMode::Front => "FRONT",
-            SpeakerMode::Back => "BACK",
+impl Settings {
+    fn defaults(config: &config::Config) -> Self {
+        Self {
+            radius: config.radius.default,
+            width: config.width.default,
+            layout: 0,
+            reverb_enabled: false, // off by default
+            filter: config.smoothing.params(),
+            preset: None,
+            tuning: 0,
+        }
+    }
+
+    fn apply_preset(&mut self, slot: usize, preset: &config::Preset, config: &config::Config) {
+        if let Some(radius) = preset.radius {
+            self.radius = radius;
+        }
+        if let Some(width) = preset.width {
+            self.width = width;
+        }
+        if let Some(layout) = preset.layout.as_ref().and_then(|name| layout_index(config, name)) {
+            self.layout = layout;
         }
+        if let Some(reverb) = preset.reverb {
+            self.reverb_enabled = reverb;
+        }
+        if let Some(smoothing) = preset.smoothing {
+            self.filter.factor = smoothing;
+        }
+        self.preset = Some(slot);
     }
 
-    fn base_angles(&self) -> (f64, f64) {
-        match self {
-            SpeakerMode::Front => (BACK_LEFT_ANGLE, BACK_RIGHT_ANGLE),
-            SpeakerMode::Back => (FRONT_LEFT_ANGLE, FRONT_RIGHT_ANGLE),
+    fn to_preset(self, name: &str, config: &config::Config) -> config::Preset {
+        config::Preset {
+            name: name.to_string(),
+            radius: Some(self.radius),
+            width: Some(self.width),
+            layout: Some(config.layouts[self.layout].name.clone()),
+            reverb: Some(self.reverb_enabled),
+            smoothing: Some(self.filter.factor),
         }
     }
+
+    // whether the settings still are what the preset set them to
+    fn matches(&self, preset: &config::Preset, config: &config::Config) -> bool {
+        let same = |value: f64, wanted: Option<f64>| wanted.is_none_or(|w| (value - w).abs() < 1e-9);
+        same(self.radius, preset.radius)
+            && same(self.width, preset.width)
+            && same(self.filter.factor, preset.smoothing)
+            && preset.layout.as_ref().is_none_or(|l| *l == config.layouts[self.layout].name)
+            && preset.reverb.is_none_or(|r| r == self.reverb_enabled)
+    }
 }
 
-struct SmoothedState {
-    yaw: f64,
-    pitch: f64,
-    roll: f64,
+fn layout_index(config: &config::Config, name: &str) -> Option<usize> {
+    config.layouts.iter().position(|l| l.name == name)
 }
 
-impl SmoothedState {
-    fn new() -> Self {
-        Self { yaw: 0.0, pitch: 0.0, roll: 0.0 }
-    }
+// layouts describe where a speaker is heard (+ = left, 0 = ahead), but the
+// spatializer's azimuth runs the other way round the front/back axis: a
+// speaker we hear ahead-left at +45 has to be sent as +135. this is the
+// only place that converts.
+fn sofa_azimuth(azimuth: f64) -> f64 {
+    wrap_degrees(180.0 - azimuth)
+}
 
-    // apply exponential smoothing
-    fn update(&mut self, raw_yaw: f64, raw_pitch: f64, raw_roll: f64) {
-        self.yaw = SMOOTHING_FACTOR * self.yaw + (1.0 - SMOOTHING_FACTOR) * raw_yaw;
-        self.pitch = SMOOTHING_FACTOR * self.pitch + (1.0 - SMOOTHING_FACTOR) * raw_pitch;
-        self.roll = SMOOTHING_FACTOR * self.roll + (1.0 - SMOOTHING_FACTOR) * raw_roll;
-    }
+// one virtual speaker relative to the head
+struct SpeakerState {
+    channel: &'static str,
+    node: &'static str, // sofa node rendering it
+    azimuth: f64,       // as heard, + = left
+    elevation: f64,
+    distance: f64,             // metres, from where the listener sits
+    gain: f64,                 // layout gain * distance gain
+    mix_controls: [String; 2], // ear mixer inputs its gain is set through
+    mix_gain: f64,             // gain scaled to the graph's mixer level
 }
 
 // holds the calculated positions for the virtual speakers relative to head
 struct SpatialState {
-    left_az: f64,
-    right_az: f64,
-    elevation: f64,
+    speakers: Vec<SpeakerState>, // one per sink channel, except LFE
     radius: f64,
     gain: f64, // volume scaling based on radius (1.0 / radius)
     reverb_gain: f64, // wet signal amount (0.0 - 1.0)
 }
 
 impl SpatialState {
-    fn from_head_tracking(yaw: f64, pitch: f64, radius: f64, mode: SpeakerMode, reverb_enabled: bool, width: f64) -> Self {
-        // get base speaker angles based on mode
-        let (left_base, right_base) = mode.base_angles();
-
-        // width > 1.0 = wider (diffused), width < 1.0 = narrower (focused)
-        let left_base_scaled = left_base * width;
-        let right_base_scaled = right_base * width;
-
-        // relative azimuth = base_pos - head_yaw
-        let left_az = left_base_scaled - yaw;
-        let right_az = right_base_scaled - yaw;
-
-        // pitch is inverted (looking up moves the source down relative to eyes)
-        let elevation = -pitch;
-
+    // `listener` is where the head is in metres, relative to the center of the
+    // speaker circle (x ahead, y left, z up)
+    fn from_head_tracking(
+        head: rotation::Quat,
+        listener: rotation::Vec3,
+        radius: f64,
+        layout: &config::Layout,
+        reverb_enabled: bool,
+        width: f64,
+        config: &config::Config,
+    ) -> Self {
         // calculate gain: inverse relationship with radius
         // at radius 1.0 = 100% gain, radius 2.0 = 50% gain, etc.
         // clamp to reasonable range
         let gain = (1.0 / radius).clamp(0.1, 2.0);
 
+        let channels = config.format().channels;
+        let mix_controls = graph::speaker_gain_controls(channels);
+        let mix_level = graph::speaker_mix_gain(mix_controls.len());
+        let speakers = channels
+            .iter()
+            .filter(|channel| !channel.is_lfe())
+            .zip(mix_controls)
+            .map(|(channel, mix_controls)| {
+                // channels the layout doesn't place stay at their standard position
+                let (base_az, base_el, speaker_gain) = match layout.speaker(channel.name) {
+                    Some(speaker) => (speaker.azimuth, speaker.elevation, speaker.gain),
+                    None => (channel.azimuth, channel.elevation, 1.0),
+                };
+                // width > 1.0 = wider (diffused), width < 1.0 = narrower (focused)
+                let world = rotation::scale(rotation::direction(base_az * width, base_el), radius);
+                // leaning in moves each speaker differently (parallax)
+                let offset = rotation::sub(world, listener);
+                let (azimuth, elevation) = rotation::to_angles(head.head_relative(offset));
+                let distance = rotation::length(offset).max(config.radius.min);
+                let gain = speaker_gain * (1.0 / distance).clamp(0.1, 2.0);
+                SpeakerState {
+                    channel: channel.name,
+                    node: channel.node,
+                    azimuth: wrap_degrees(azimuth),
+                    elevation,
+                    distance,
+                    gain,
+                    mix_controls,
+                    mix_gain: mix_level * gain,
+                }
+            })
+            .collect();
+
         // calculate reverb gain using square-root curve for natural progression
         // sqrt gives more reverb early on, then tapers - matches physical acoustics
         let reverb_gain = if reverb_enabled {
-            let normalized = ((radius - MIN_RADIUS) / (MAX_RADIUS - MIN_RADIUS)).clamp(0.0, 1.0);
-            MIN_REVERB + normalized.sqrt() * (MAX_REVERB - MIN_REVERB)
+            let (min_radius, max_radius) = (config.radius.min, config.radius.max);
+            let normalized = if max_radius > min_radius {
+                ((radius - min_radius) / (max_radius - min_radius)).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            config.reverb.min + normalized.sqrt() * (config.reverb.max - config.reverb.min)
         } else {
             0.0 // reverb disabled
         };
 
-        Self { left_az, right_az, elevation, radius, gain, reverb_gain }
+        Self { speakers, radius, gain, reverb_gain }
+    }
+
+    fn speaker(&self, channel: &str) -> Option<&SpeakerState> {
+        self.speakers.iter().find(|s| s.channel == channel)
+    }
+}
+
+// opentrack's x (right), y (up), z (back) in tracker units, as metres in our
+// axes (x ahead, y left, z up)
+fn tracker_position(x: f64, y: f64, z: f64, config: &config::Config) -> rotation::Vec3 {
+    if !config.translation.enabled {
+        return [0.0; 3];
+    }
+    rotation::scale([-z, -x, y], config.translation.scale)
+}
+
+// state of the pipewire link, shown in the connection panel
+struct LinkStatus {
+    backend: &'static str,
+    channels: &'static [graph::Channel], // what the graph was generated for
+    node: Option<NodeInfo>,
+    warnings: Vec<&'static str>, // features the linked graph can't support
+    latency_samples: Vec<f64>,
+    latency_ms: f64,
+    push_errors: u64,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    last_success: Option<Instant>,
+    ever_linked: bool,
+    reconnects: u64,
+}
+
+impl LinkStatus {
+    fn new(backend: &'static str, channels: &'static [graph::Channel]) -> Self {
+        Self {
+            backend,
+            channels,
+            node: None,
+            warnings: Vec::new(),
+            latency_samples: Vec::with_capacity(30),
+            latency_ms: 0.0,
+            push_errors: 0,
+            consecutive_failures: 0,
+            last_error: None,
+            last_success: None,
+            ever_linked: false,
+            reconnects: 0,
+        }
+    }
+
+    fn link(&mut self, node: NodeInfo) {
+        if self.ever_linked {
+            self.reconnects += 1;
+        }
+        self.ever_linked = true;
+        self.warnings = missing_features(&node, self.channels);
+        self.node = Some(node);
+        self.consecutive_failures = 0;
+    }
+
+    // back to SEARCHING, the main loop will re-resolve the node
+    fn unlink(&mut self, reason: String) {
+        self.node = None;
+        self.warnings.clear();
+        self.consecutive_failures = 0;
+        self.last_error = Some(reason);
+    }
+
+    fn record_push(&mut self, result: Result<f64, String>) {
+        match result {
+            Ok(cmd_latency) => {
+                // track latency samples for averaging
+                self.latency_samples.push(cmd_latency);
+                if self.latency_samples.len() > 30 {
+                    self.latency_samples.remove(0);
+                }
+                self.latency_ms = self.latency_samples.iter().sum::<f64>() / self.latency_samples.len() as f64;
+                self.last_error = None;
+                self.last_success = Some(Instant::now());
+                self.consecutive_failures = 0;
+            }
+            Err(e) => {
+                self.push_errors += 1;
+                self.consecutive_failures += 1;
+                if self.consecutive_failures >= MAX_PUSH_FAILURES && self.node.is_some() {
+                    self.unlink(format!("link lost: {}", e));
+                } else {
+                    self.last_error = Some(e);
+                }
+            }
+        }
     }
 }
 
@@ -147,6 +308,30 @@ fn clear_screen() {
         .ok();
 }
 
+// at most `max` characters; error text from pw-cli or the os isn't always ascii
+fn truncate_chars(text: &str, max: usize) -> String {
+    text.chars().take(max).collect()
+}
+
+// split at spaces into lines of at most `width` characters; a word longer
+// than that (a path) is split across lines
+fn wrap_words(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => {
+                let chars: Vec<char> = word.chars().collect();
+                lines.extend(chars.chunks(width.max(1)).map(|chunk| chunk.iter().collect::<String>()));
+            }
+        }
+    }
+    lines
+}
+
 // helper: calculate string width ignoring ansi color codes
 // fixes border alignment by counting emojis as 2 width
 fn get_visible_width(s: &str) -> usize {
@@ -177,10 +362,9 @@ fn render_azimuth_bar(azimuth: f64, width: usize) -> String {
     let mut bar = String::with_capacity(width + 20);
     bar.push('[');
 
-    // map azimuth (-180..180) to bar position
-    // clamp to reasonable range for display
-    let clamped = azimuth.clamp(-90.0, 90.0);
-    let normalized = (clamped + 90.0) / 180.0; // 0..1
+    // map azimuth (+180 left end .. -180 right end, ahead in the middle)
+    // to bar position; both ends are behind the head
+    let normalized = ((180.0 - wrap_degrees(azimuth)) / 360.0).clamp(0.0, 1.0); // 0..1
     let pos = (normalized * (width - 1) as f64).round() as usize;
     let center_idx = width / 2;
 
@@ -198,6 +382,15 @@ fn render_azimuth_bar(azimuth: f64, width: usize) -> String {
     bar
 }
 
+// why the angles below aren't moving, if the tracker went quiet
+fn no_tracker_message(since_last_packet: Option<Duration>, listen: SocketAddr) -> Option<String> {
+    match since_last_packet {
+        None => Some(format!("waiting for OpenTrack on {}", listen)),
+        Some(since) if since >= TRACKER_TIMEOUT => Some(format!("last packet {:.1}s ago", since.as_secs_f64())),
+        Some(_) => None,
+    }
+}
+
 // render an elevation indicator
 fn render_elevation_indicator(elevation: f64) -> &'static str {
     if elevation > 10.0 {
@@ -209,88 +402,102 @@ fn render_elevation_indicator(elevation: f64) -> &'static str {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_dashboard(
-    smoothed: &SmoothedState,
-    raw_yaw: f64,
-    raw_pitch: f64,
-    raw_roll: f64,
+    smoothed: &filter::Pose,
+    raw: &filter::Pose,
+    predicted: &filter::Pose,
+    center: &filter::Pose,
+    listener: rotation::Vec3,
     spatial: &SpatialState,
     fps: f64,
-    node_id: &Option<String>,
-    latency_ms: f64,
-    packets: u64,
-    mode: SpeakerMode,
-    reverb_enabled: bool,
-    width: f64,
+    link: &LinkStatus,
+    config: &config::Config,
+    packets: &packet::PacketStats,
+    since_last_packet: Option<Duration>,
+    settings: &Settings,
+    presets: &[config::Preset],
+    notice: Option<&str>,
 ) {
     clear_screen();
+    let Settings { layout, reverb_enabled, width, .. } = *settings;
 
     let draw_row = |content: &str| {
-        let inner_target = 66;
+        let inner_target: usize = 66;
         let visible = get_visible_width(content);
-        let padding = if inner_target > visible { inner_target - visible } else { 0 };
+        let padding = inner_target.saturating_sub(visible);
         print!("\x1B[1;96m║\x1B[0m{}{}\x1B[1;96m║\x1B[0m\r\n", content, " ".repeat(padding));
     };
 
     let pad_field = |text: String, width: usize| -> String {
         let vis = get_visible_width(&text);
-        let p = if width > vis { width - vis } else { 0 };
+        let p = width.saturating_sub(vis);
         format!("{}{}", text, " ".repeat(p))
     };
 
     print!("\x1B[1;96m╔══════════════════════════════════════════════════════════════════╗\x1B[0m\r\n");
 
-    let title = "\x1B[1;37m🎧 SPATIAL AUDIO ENGINE (HRTF STEREO)\x1B[0m";
-    let t_vis = get_visible_width(title);
+    let title = format!("\x1B[1;37m🎧 SPATIAL AUDIO ENGINE (HRTF {})\x1B[0m", config.format().name.to_uppercase());
+    let t_vis = get_visible_width(&title);
     let t_pad = (66 - t_vis) / 2;
     print!("\x1B[1;96m║\x1B[0m{}{}{}\x1B[1;96m║\x1B[0m\r\n", " ".repeat(t_pad), title, " ".repeat(66 - t_vis - t_pad));
     print!("\x1B[1;96m╠══════════════════════════════════════════════════════════════════╣\x1B[0m\r\n");
 
     draw_row(&format!("  {}", "\x1B[1;33m🧭 HEAD TRACKING\x1B[0m"));
     draw_row("");
+    if let Some(message) = no_tracker_message(since_last_packet, config.tracker.listen) {
+        draw_row(&format!("    \x1B[1;31m⚠ NO TRACKER DATA\x1B[0m  {}", message));
+        draw_row("");
+    }
     draw_row(&format!("    \x1B[90mRAW:\x1B[0m     Yaw={:>7.1}°  Pitch={:>7.1}°  Roll={:>7.1}°",
-                      raw_yaw, raw_pitch, raw_roll));
+                      raw.yaw, raw.pitch, raw.roll));
     draw_row(&format!("    \x1B[1;37mSMOOTH:\x1B[0m  Yaw={:>7.1}°  Pitch={:>7.1}°  Roll={:>7.1}°",
                       smoothed.yaw, smoothed.pitch, smoothed.roll));
+    if config.prediction.lookahead_ms > 0.0 {
+        draw_row(&format!("    \x1B[1;37mPREDICT:\x1B[0m Yaw={:>7.1}°  vs measured {:>7.1}°  \x1B[90m(+{:.0} ms)\x1B[0m",
+                          predicted.yaw, smoothed.yaw, config.prediction.lookahead_ms));
+    }
+    let auto = if config.recenter.auto { "  \x1B[90m(auto)\x1B[0m" } else { "" };
+    draw_row(&format!("    \x1B[90mCENTER:\x1B[0m  Yaw={:>7.1}°  Pitch={:>7.1}°  Roll={:>7.1}°{}",
+                      center.yaw, center.pitch, center.roll, auto));
+    if config.translation.enabled {
+        draw_row(&format!("    \x1B[90mLEAN:\x1B[0m    Ahead={:>+6.2}m  Left={:>+6.2}m  Up={:>+6.2}m",
+                          listener[0], listener[1], listener[2]));
+    }
 
     draw_row("");
     print!("\x1B[1;96m╠══════════════════════════════════════════════════════════════════╣\x1B[0m\r\n");
 
-    let mode_color = match mode {
-        SpeakerMode::Front => "\x1B[1;32m",
-        SpeakerMode::Back => "\x1B[1;33m",
-    };
-    draw_row(&format!("  \x1B[1;35m🔊 VIRTUAL SPEAKERS\x1B[0m  [{}{}°\x1B[0m]", mode_color, mode.label()));
+    let layout = &config.layouts[layout];
+    draw_row(&format!("  \x1B[1;35m🔊 VIRTUAL SPEAKERS\x1B[0m  [\x1B[1;32m{}\x1B[0m]", layout.name));
     draw_row("");
 
-    let adjust_display_azimuth = |a: f64| -> f64 {
-        let mut x = a;
-        // normalize to -180..180
-        while x <= -180.0 { x += 360.0; }
-        while x > 180.0 { x -= 360.0; }
-        // so it doesnt clamp to the end
-        if x > 90.0 {
-            x -= 180.0;
-        } else if x < -90.0 {
-            x += 180.0;
-        }
-        x
+    let preset = match settings.preset.and_then(|slot| presets.get(slot).map(|p| (slot, p))) {
+        Some((slot, preset)) => {
+            let modified = if settings.matches(preset, config) { "" } else { " \x1B[90m(modified)\x1B[0m" };
+            format!("\x1B[1;36m{}\x1B[0m [{}]{}", preset.name, slot + 1, modified)
+        }
+        None => "\x1B[90mnone\x1B[0m".to_string(),
     };
+    draw_row(&format!("    \x1B[1;37mPreset:\x1B[0m    {}", preset));
+    draw_row("");
 
-    let left_display = adjust_display_azimuth(spatial.right_az);
-    let right_display = adjust_display_azimuth(spatial.left_az);
-
-    let l_bar = render_azimuth_bar(left_display, 24);
-    draw_row(&format!("    \x1B[1;34mLeft Speaker:\x1B[0m  {}  {:>+6.1}°", l_bar, left_display));
-
-    let r_bar = render_azimuth_bar(right_display, 24);
-    draw_row(&format!("    \x1B[1;35mRight Speaker:\x1B[0m {}  {:>+6.1}°", r_bar, right_display));
+    // where each speaker is heard right now, with its elevation
+    for speaker in &spatial.speakers {
+        let bar = render_azimuth_bar(speaker.azimuth, 24);
+        draw_row(&format!(
+            "    \x1B[1;34m{:<3}\x1B[0m {}  {:>+6.1}°  {:>+5.1}° {:>5.2}m {}",
+            speaker.channel,
+            bar,
+            speaker.azimuth,
+            speaker.elevation,
+            speaker.distance,
+            render_elevation_indicator(speaker.elevation)
+        ));
+    }
 
     draw_row("");
 
-    let elev_indicator = render_elevation_indicator(spatial.elevation);
-    draw_row(&format!("    \x1B[1;37mElevation:\x1B[0m {:>+6.1}°  {}", spatial.elevation, elev_indicator));
-
     let gain_pct = spatial.gain * 100.0;
     draw_row(&format!("    \x1B[1;37mRadius:\x1B[0m    {:>6.2}m  (Gain: {:>3.0}%)", spatial.radius, gain_pct));
 
@@ -314,8 +521,10 @@ fn render_dashboard(
     };
     draw_row(&format!("    \x1B[1;37mWidth:\x1B[0m    {:>6.0}%  ({})", width_pct, width_desc));
 
-    let sep_angle = (spatial.left_az - spatial.right_az).abs();
-    draw_row(&format!("    \x1B[1;37mSeparation:\x1B[0m {:>5.1}°  (speaker spread)", sep_angle));
+    if let (Some(left), Some(right)) = (spatial.speaker("FL"), spatial.speaker("FR")) {
+        let sep_angle = wrap_degrees(left.azimuth - right.azimuth).abs();
+        draw_row(&format!("    \x1B[1;37mSeparation:\x1B[0m {:>5.1}°  (speaker spread)", sep_angle));
+    }
 
     draw_row("");
     print!("\x1B[1;96m╠══════════════════════════════════════════════════════════════════╣\x1B[0m\r\n");
@@ -323,11 +532,37 @@ fn render_dashboard(
     draw_row(&format!("  {}", "\x1B[1;32m📡 CONNECTION\x1B[0m"));
     draw_row("");
 
-    let status = match node_id {
-        Some(id) => format!("\x1B[1;32m✓ LINKED\x1B[0m to Node \x1B[1;37m{}\x1B[0m ({})", id, SPATIALIZER_NODE_NAME),
-        None => format!("\x1B[1;31m✗ SEARCHING\x1B[0m for '{}'...", SPATIALIZER_NODE_NAME),
+    let status = match &link.node {
+        Some(node) => format!("\x1B[1;32m✓ LINKED\x1B[0m to Node \x1B[1;37m{}\x1B[0m ({})", node.id, node.name),
+        None => format!("\x1B[1;31m✗ SEARCHING\x1B[0m for '{}'...", config.pipewire.node_name),
     };
     draw_row(&format!("    {}", status));
+    draw_row(&format!("    \x1B[90mTracker:\x1B[0m listening on \x1B[1;37m{}\x1B[0m", config.tracker.listen));
+    if let Some(node) = &link.node {
+        let class = node.media_class.as_deref().unwrap_or("?");
+        let playback = match &node.playback {
+            Some((id, name)) => format!("{} ({})", name, id),
+            None => "?".to_string(),
+        };
+        draw_row(&format!("    \x1B[90mClass:\x1B[0m {}   \x1B[90mPlayback:\x1B[0m {}", class, playback));
+    }
+    for warning in &link.warnings {
+        draw_row(&format!("    \x1B[1;33m⚠ {}\x1B[0m", warning));
+    }
+    let controls = match &link.node {
+        Some(node) if !node.controls.is_empty() => node.controls.len().to_string(),
+        _ => "?".to_string(),
+    };
+    draw_row(&format!("    \x1B[90mBackend:\x1B[0m {}   \x1B[90mControls:\x1B[0m {}   \x1B[90mFailed pushes:\x1B[0m {}",
+                      link.backend, controls, link.push_errors));
+    let last_push = match link.last_success {
+        Some(t) => format!("{:.1}s ago", t.elapsed().as_secs_f64()),
+        None => "never".to_string(),
+    };
+    draw_row(&format!("    \x1B[90mReconnects:\x1B[0m {}   \x1B[90mLast push:\x1B[0m {}", link.reconnects, last_push));
+    if let Some(err) = &link.last_error {
+        draw_row(&format!("    \x1B[1;31m✗ {}\x1B[0m", truncate_chars(err, 50)));
+    }
 
     draw_row("");
     print!("\x1B[1;96m╠══════════════════════════════════════════════════════════════════╣\x1B[0m\r\n");
@@ -338,22 +573,43 @@ fn render_dashboard(
     let col_width = 25;
 
     let fps_str = pad_field(format!("FPS: \x1B[1;37m{:>5.1}\x1B[0m", fps), col_width);
-    let lat_str = format!("Latency: \x1B[1;37m{:>5.2}ms\x1B[0m", latency_ms);
+    let lat_str = format!("Latency: \x1B[1;37m{:>5.2}ms\x1B[0m", link.latency_ms);
     draw_row(&format!("    {}  │  {}", fps_str, lat_str));
 
-    let pkts_str = pad_field(format!("Packets: \x1B[1;37m{}\x1B[0m", packets), col_width);
-    let thresh_str = format!("Threshold: \x1B[1;37m{:.1}°\x1B[0m", CHANGE_THRESHOLD);
+    let pkts_str = pad_field(format!("Packets: \x1B[1;37m{}\x1B[0m", packets.received), col_width);
+    let thresh_str = format!("Threshold: \x1B[1;37m{:.1}°\x1B[0m", config.smoothing.change_threshold);
     draw_row(&format!("    {}  │  {}", pkts_str, thresh_str));
 
-    let smooth_str = pad_field(format!("Smoothing: \x1B[1;37m{:.0}%\x1B[0m", SMOOTHING_FACTOR * 100.0), col_width);
-    draw_row(&format!("    {}  │", smooth_str));
+    // rejected packets stand out, they mean a misconfigured sender
+    let bad = |count: u64| if count > 0 { format!("\x1B[1;33m{}\x1B[0m", count) } else { format!("\x1B[1;37m{}\x1B[0m", count) };
+    let short_str = pad_field(format!("Short: {}", bad(packets.short)), col_width);
+    draw_row(&format!("    {}  │  Malformed: {}", short_str, bad(packets.malformed)));
+    let stale_str = pad_field(format!("Stale: \x1B[1;37m{}\x1B[0m", packets.stale), col_width);
+    draw_row(&format!("    {}  │  \x1B[90m(dropped for a newer pose)\x1B[0m", stale_str));
+    if let Some(err) = &packets.last_error {
+        draw_row(&format!("    \x1B[90mLast rejected: {}\x1B[0m", truncate_chars(err, 50)));
+    }
+
+    let filters: Vec<&str> = config.smoothing.filters.iter().map(|kind| kind.name()).collect();
+    let filters = if filters.is_empty() { "none".to_string() } else { filters.join(" → ") };
+    draw_row(&format!("    Filters: \x1B[1;37m{}\x1B[0m", filters));
+    if let Some((kind, param)) = filter::tunables(&config.smoothing.filters).get(settings.tuning) {
+        draw_row(&format!("    Tuning:  \x1B[1;37m{} {}\x1B[0m = \x1B[1;37m{}\x1B[0m  \x1B[90m(F next, [ ] adjust)\x1B[0m",
+                          kind.name(), param.name(), param.format(&settings.filter)));
+    }
 
     draw_row("");
     print!("\x1B[1;96m╠══════════════════════════════════════════════════════════════════╣\x1B[0m\r\n");
 
     draw_row(&format!("  {}", "\x1B[1;90m⌨ CONTROLS\x1B[0m"));
-    draw_row("    \x1B[90m↑/↓\x1B[0m Radius   \x1B[90m←/→\x1B[0m Width   \x1B[90mW\x1B[0m Front   \x1B[90mS\x1B[0m Back");
-    draw_row("    \x1B[90mR\x1B[0m Reverb   \x1B[90mQ/Esc\x1B[0m Quit");
+    draw_row("    \x1B[90m↑/↓\x1B[0m Radius   \x1B[90m←/→\x1B[0m Width   \x1B[90mL\x1B[0m Layout   \x1B[90mW\x1B[0m Front   \x1B[90mS\x1B[0m Back");
+    draw_row("    \x1B[90mR\x1B[0m Reverb   \x1B[90m1-9\x1B[0m Preset   \x1B[90mP\x1B[0m Save preset   \x1B[90mF [ ]\x1B[0m Filter");
+    draw_row("    \x1B[90mC\x1B[0m Recenter   \x1B[90mQ/Esc\x1B[0m Quit");
+    if let Some(notice) = notice {
+        for line in wrap_words(notice, 60).iter().take(3) {
+            draw_row(&format!("    \x1B[1;33m{}\x1B[0m", line));
+        }
+    }
     print!("\x1B[1;96m╚══════════════════════════════════════════════════════════════════╝\x1B[0m\r\n");
 }
 
@@ -361,270 +617,715 @@ fn render_dashboard(
 // PIPEWIRE CONTROL
 // ==============================================================================
 
-fn find_spatializer_node() -> Option<String> {
-        // run 'pw-cli ls Node'
-    let output = Command::new("pw-cli").args(["ls", "Node"]).output().ok()?;
-    let text = String::from_utf8_lossy(&output.stdout);
+// the filter-chain controls we drive, as (node:control, value) pairs
+fn build_params(spatial: &SpatialState) -> Vec<(String, f64)> {
+    // sets params for every spatializer node
+    // uses dynamic radius and includes gain for reverb simulation
+    let mut params = Vec::new();
+    for speaker in &spatial.speakers {
+        params.push((graph::control(speaker.node, "Azimuth"), sofa_azimuth(speaker.azimuth)));
+        params.push((graph::control(speaker.node, "Elevation"), speaker.elevation));
+        params.push((graph::control(speaker.node, "Radius"), speaker.distance));
+        for control in &speaker.mix_controls {
+            params.push((control.clone(), speaker.mix_gain));
+        }
+    }
+    let dry_gain = 1.0 - spatial.reverb_gain;
+    params.extend([
+        (graph::control(graph::FINAL_MIX_LEFT, "Gain 1"), dry_gain),
+        (graph::control(graph::FINAL_MIX_LEFT, "Gain 2"), spatial.reverb_gain),
+        (graph::control(graph::FINAL_MIX_RIGHT, "Gain 1"), dry_gain),
+        (graph::control(graph::FINAL_MIX_RIGHT, "Gain 2"), spatial.reverb_gain),
+    ]);
+    params
+}
 
-    let mut current_id = String::new();
+// features that need controls the loaded graph doesn't have
+fn missing_features(node: &NodeInfo, channels: &[graph::Channel]) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+    let all = |controls: &[(&str, &str)]| controls.iter().all(|(n, c)| node.supports(&graph::control(n, c)));
+    let every_speaker = |control| {
+        channels.iter().filter(|ch| !ch.is_lfe()).all(|ch| node.supports(&graph::control(ch.node, control)))
+    };
 
-    // simple state machine parser (no external deps)
-    for line in text.lines() {
-        let trim = line.trim();
-        if trim.starts_with("id") {
-            // "id 36, type PipeWire:Interface:Node..."
-            if let Some(id_part) = trim.split_whitespace().nth(1) {
-                current_id = id_part.trim_matches(',').to_string();
-            }
-        }
-        // check for our target node name
-        if trim.contains("node.name") && trim.contains(SPATIALIZER_NODE_NAME) {
-            return Some(current_id);
-        }
+    if !every_speaker("Azimuth") {
+        missing.push("No Azimuth controls: head tracking has no effect");
+    }
+    if !every_speaker("Radius") {
+        missing.push("No Radius controls: distance is fixed");
+    }
+    if !graph::speaker_gain_controls(channels).iter().flatten().all(|control| node.supports(control)) {
+        missing.push("No speaker mixer gains: distance doesn't change volume");
     }
-    None
+    if !all(&[
+        (graph::FINAL_MIX_LEFT, "Gain 1"),
+        (graph::FINAL_MIX_LEFT, "Gain 2"),
+        (graph::FINAL_MIX_RIGHT, "Gain 1"),
+        (graph::FINAL_MIX_RIGHT, "Gain 2"),
+    ]) {
+        missing.push("No dry/wet mixer: reverb unavailable");
+    }
+    missing
 }
 
-fn update_pipewire(id: &str, spatial: &SpatialState) {
-    // build the json for the stereo filter-chain
-    // sets params for both 'spat_left' and 'spat_right' nodes
-    // uses dynamic radius and includes gain for reverb simulation
-    let dry_gain = 1.0 - spatial.reverb_gain;
-    let json_payload = format!(
-        "{{ \"params\": [ \
-            \"spat_left:Azimuth\", {:.2}, \
-            \"spat_left:Elevation\", {:.2}, \
-            \"spat_left:Radius\", {:.2}, \
-            \"spat_left:Gain\", {:.2}, \
-            \"spat_right:Azimuth\", {:.2}, \
-            \"spat_right:Elevation\", {:.2}, \
-            \"spat_right:Radius\", {:.2}, \
-            \"spat_right:Gain\", {:.2}, \
-            \"final_mix_l:Gain 1\", {:.2}, \
-            \"final_mix_l:Gain 2\", {:.2}, \
-            \"final_mix_r:Gain 1\", {:.2}, \
-            \"final_mix_r:Gain 2\", {:.2} \
-        ] }}",
-        spatial.left_az, spatial.elevation, spatial.radius, spatial.gain,
-        spatial.right_az, spatial.elevation, spatial.radius, spatial.gain,
-        dry_gain, spatial.reverb_gain,
-        dry_gain, spatial.reverb_gain
+// the graph as the program starts out: speakers where the default state puts
+// them with the head facing forward, reverb off
+fn startup_graph<'a>(config: &'a config::Config, sofa_path: &'a str, ir_path: &'a str) -> graph::GraphSpec<'a> {
+    let spatial = SpatialState::from_head_tracking(
+        rotation::Quat::IDENTITY,
+        [0.0; 3],
+        config.radius.default,
+        &config.layouts[0],
+        false,
+        config.width.default,
+        config,
     );
+    let format = config.format();
+    let speakers = format
+        .channels
+        .iter()
+        .filter(|channel| !channel.is_lfe())
+        .zip(&spatial.speakers)
+        .map(|(channel, speaker)| graph::SpeakerSpec {
+            node: speaker.node,
+            label: channel.label,
+            heard: speaker.azimuth,
+            azimuth: sofa_azimuth(speaker.azimuth),
+            elevation: speaker.elevation,
+            radius: speaker.distance,
+            gain: speaker.gain,
+        })
+        .collect();
+    graph::GraphSpec {
+        node_name: &config.pipewire.node_name,
+        format,
+        sofa_path,
+        ir_path,
+        speakers,
+        lfe_gain: config.pipewire.lfe_gain,
+        dry_gain: 1.0 - spatial.reverb_gain,
+        wet_gain: spatial.reverb_gain,
+    }
+}
 
-    // spawn async (fire and forget) to prevent frame drops
-    // redirect stdout/stderr to null to prevent tui artifacts
-    Command::new("pw-cli")
-        .args(["set-param", id, "Props", &json_payload])
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()
-        .ok();
+fn generate_conf(options: &Options, config: &config::Config) -> Result<(), String> {
+    let sofa_path = options.sofa_path.as_deref().unwrap_or(graph::DEFAULT_SOFA_PATH);
+    let ir_path = options.ir_path.as_deref().unwrap_or(graph::DEFAULT_IR_PATH);
+    let conf = graph::generate_conf(&startup_graph(config, sofa_path, ir_path));
+    match &options.output {
+        Some(path) => std::fs::write(path, conf).map_err(|e| format!("Failed to write '{}': {}", path, e)),
+        None => {
+            print!("{}", conf);
+            Ok(())
+        }
+    }
+}
+
+// push the current spatial state to the linked node and record the outcome,
+// leaving out params for controls the graph doesn't have
+fn push_spatial(backend: &mut dyn OutputBackend, link: &mut LinkStatus, spatial: &SpatialState) {
+    let Some(node) = &link.node else { return };
+    let params: Vec<(String, f64)> = build_params(spatial)
+        .into_iter()
+        .filter(|(control, _)| node.supports(control))
+        .collect();
+    if params.is_empty() {
+        return;
+    }
+    let id = node.id.clone();
+    match backend.set_params(&id, &params) {
+        Ok(Pushed::Done(latency)) => link.record_push(Ok(latency)),
+        // counted once poll_backend hears back
+        Ok(Pushed::Queued) => {}
+        Err(e) => link.record_push(Err(e)),
+    }
+}
+
+// let backends with queued work (pw-cli session) make progress, and count
+// queued pushes once they're acknowledged or fail
+fn poll_backend(backend: &mut dyn OutputBackend, link: &mut LinkStatus) {
+    match backend.poll() {
+        Ok(Some(latency)) => link.record_push(Ok(latency)),
+        Ok(None) => {}
+        Err(e) => link.record_push(Err(e)),
+    }
+}
+
+// look the node up again, see relink
+fn check_node(backend: &mut dyn OutputBackend, link: &mut LinkStatus, node_name: &str) -> bool {
+    let found = backend.find_node(node_name);
+    relink(backend, link, found)
+}
+
+// link the node a lookup found if it's new or its id changed, drop it if it's
+// gone. true when a node was (re)linked
+fn relink(backend: &mut dyn OutputBackend, link: &mut LinkStatus, found: Option<NodeInfo>) -> bool {
+    let current_id = link.node.as_ref().map(|node| node.id.clone());
+    match (found, current_id) {
+        (Some(found), Some(current)) if found.id == current => false,
+        (Some(mut found), _) => {
+            // fall back to reading the controls back if discovery didn't list them
+            if found.controls.is_empty() {
+                if let Ok(params) = backend.read_params(&found.id) {
+                    found.controls = params.into_iter().map(|(name, _)| name).collect();
+                }
+            }
+            link.link(found);
+            true
+        }
+        (None, Some(_)) => {
+            link.unlink("node disappeared".to_string());
+            false
+        }
+        (None, None) => false,
+    }
 }
 
 // ==============================================================================
 // MAIN
 // ==============================================================================
 
+mod backend;
+mod config;
+mod filter;
+mod graph;
+mod install;
+#[cfg(feature = "native")]
+mod native;
+mod packet;
+mod paths;
+mod pwdump;
+mod recenter;
+mod rotation;
+mod state;
+
+use backend::{NodeInfo, OutputBackend, Pushed};
+use rotation::wrap_degrees;
+
 fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}\n", e);
+            print_usage();
+            std::process::exit(2);
+        }
+    };
+    if options.show_help {
+        print_usage();
+        return;
+    }
+
+    let config = match load_config(&options) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let result = match options.command {
+        Subcommand::Run => run_dashboard(&options, &config),
+        Subcommand::GenerateConf => generate_conf(&options, &config),
+        Subcommand::Install => run_install(&options, &config),
+        Subcommand::Uninstall => install::uninstall(options.restart_pipewire),
+        Subcommand::Doctor => {
+            if !install::doctor(&config.pipewire.node_name, config.tracker.listen) {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// config file, with command line overrides applied on top
+fn load_config(options: &Options) -> Result<config::Config, String> {
+    let mut config = config::load(options.config_path.as_deref().map(Path::new))?;
+    if let Some(listen) = options.listen {
+        config.tracker.listen = listen;
+    }
+    Ok(config)
+}
+
+fn run_install(options: &Options, config: &config::Config) -> Result<(), String> {
+    let sofa = paths::installed_sofa();
+    let ir = paths::installed_ir();
+    let conf = graph::generate_conf(&startup_graph(config, &sofa.to_string_lossy(), &ir.to_string_lossy()));
+    install::install(&conf, options.sofa_path.as_deref(), options.ir_path.as_deref(), options.restart_pipewire)
+}
+
+fn run_dashboard(options: &Options, config: &config::Config) -> Result<(), String> {
+    let mut backend = open_backend(options)?;
+
+    // the alternate screen would hide anything printed now, a bad state file
+    // is reported in the dashboard instead
+    let state_path = paths::state_file();
+    let mut notice = None;
+    let settings = if options.restore_state {
+        state::load(&state_path, config).unwrap_or_else(|e| {
+            // toml errors go on for lines, the first says what's wrong
+            notice = Some(format!("{}, starting from defaults", e.lines().next().unwrap_or_default()));
+            Settings::defaults(config)
+        })
+    } else {
+        Settings::defaults(config)
+    };
+
     // enable raw mode for keyboard input
     terminal::enable_raw_mode().expect("Failed to enable raw mode");
     stdout().execute(EnterAlternateScreen).expect("Failed to enter alternate screen");
 
     // make sure we cleanup on exit
-    let result = run_main_loop();
+    let result = run_main_loop(backend.as_mut(), config, settings, notice);
 
     // cleanup terminal
     terminal::disable_raw_mode().ok();
     stdout().execute(LeaveAlternateScreen).ok();
 
-    if let Err(e) = result {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    // keep the last settings for next time; not worth failing the run over
+    let (settings, presets) = result?;
+    if let Err(e) = state::save(&state_path, &settings, config, &presets) {
+        eprintln!("Warning: {}", e);
     }
+    Ok(())
 }
 
-fn run_main_loop() -> Result<(), String> {
+// runs until quit, returning the settings to save and the presets they refer to
+fn run_main_loop(
+    backend: &mut dyn OutputBackend,
+    config: &config::Config,
+    mut settings: Settings,
+    mut notice: Option<String>,
+) -> Result<(Settings, Vec<config::Preset>), String> {
+    let listen_addr = config.tracker.listen;
     clear_screen();
     print!("\x1B[1;96m╔══════════════════════════════════════════════════════════════════╗\x1B[0m\r\n");
     print!("\x1B[1;96m║\x1B[0m{:^66}\x1B[1;96m║\x1B[0m\r\n", "\x1B[1;37m🎧 SPATIAL AUDIO ENGINE\x1B[0m");
     print!("\x1B[1;96m╠══════════════════════════════════════════════════════════════════╣\x1B[0m\r\n");
     print!("\x1B[1;96m║\x1B[0m{:66}\x1B[1;96m║\x1B[0m\r\n", "");
-    print!("\x1B[1;96m║\x1B[0m  {:<64}\x1B[1;96m║\x1B[0m\r\n", "🔌 Binding to UDP port 4242...");
+    print!("\x1B[1;96m║\x1B[0m  {:<64}\x1B[1;96m║\x1B[0m\r\n", format!("🔌 Binding to UDP {}...", listen_addr));
     stdout().flush().ok();
 
-    let socket = match UdpSocket::bind("127.0.0.1:4242") {
+    let socket = match UdpSocket::bind(listen_addr) {
         Ok(s) => {
             print!("\x1B[1;96m║\x1B[0m  {:<64}\x1B[1;96m║\x1B[0m\r\n", "\x1B[1;32m✓ Socket bound successfully!\x1B[0m");
             s
         }
         Err(e) => {
-            return Err(format!("Failed to bind socket: {}", e));
+            return Err(format!("Failed to bind socket to {}: {}", listen_addr, e));
         }
     };
 
-    socket.set_read_timeout(Some(Duration::from_millis(10))).ok();
-
     print!("\x1B[1;96m║\x1B[0m{:66}\x1B[1;96m║\x1B[0m\r\n", "");
     print!("\x1B[1;96m║\x1B[0m  {:<64}\x1B[1;96m║\x1B[0m\r\n",
-             format!("🔍 Searching for '{}'...", SPATIALIZER_NODE_NAME));
+             format!("🔍 Searching for '{}'...", config.pipewire.node_name));
     print!("\x1B[1;96m║\x1B[0m  {:<64}\x1B[1;96m║\x1B[0m\r\n", "\x1B[1;33m⏳ Waiting for OpenTrack data...\x1B[0m");
-    print!("\x1B[1;96m║\x1B[0m     {:<61}\x1B[1;96m║\x1B[0m\r\n", "Make sure OpenTrack is sending UDP to 127.0.0.1:4242");
+    print!("\x1B[1;96m║\x1B[0m     {:<61}\x1B[1;96m║\x1B[0m\r\n", format!("Make sure OpenTrack is sending UDP to {}", listen_addr));
     print!("\x1B[1;96m║\x1B[0m{:66}\x1B[1;96m║\x1B[0m\r\n", "");
     print!("\x1B[1;96m╚══════════════════════════════════════════════════════════════════╝\x1B[0m\r\n");
     stdout().flush().ok();
 
-    let mut buf = [0u8; 48];
-    let mut smoothed = SmoothedState::new();
+    let mut filters = filter::FilterChain::new(&config.smoothing.filters);
+    let mut predictor = filter::Predictor::default();
+    let mut recenter = recenter::Recenter::default();
+    let mut last_packet: Option<Instant> = None;
+
+    // the latest pose, kept between wakeups so a rate-limited one is sent later
+    let (mut raw, mut smoothed, mut predicted) = (filter::Pose::default(), filter::Pose::default(), filter::Pose::default());
+    let mut last_position: rotation::Vec3 = [0.0; 3];
+    let mut pose_pending = false;
 
     // state tracking
-    let mut cached_node_id: Option<String> = None;
-    let mut last_node_search = Instant::now();
+    let mut link = LinkStatus::new(backend.name(), config.format().channels);
     let mut last_update_time = Instant::now();
+    let update_rate = Duration::from_millis(config.smoothing.update_rate_ms);
+    // the first draw replaces the splash screen straight away
+    let mut next_render = Instant::now();
 
     // fps calculation
     let mut frame_count: u32 = 0;
     let mut last_fps_calc = Instant::now();
     let mut current_fps: f64 = 0.0;
 
-    // packet counter
-    let mut packet_count: u64 = 0;
+    // packet counters
+    let mut packet_stats = packet::PacketStats::default();
 
     // don't spam pipewire if head hasn't moved
-    let mut last_sent_yaw: f64 = f64::MAX;
-    let mut last_sent_pitch: f64 = f64::MAX;
+    let mut last_sent_head: Option<rotation::Quat> = None;
+    let mut last_sent_listener: rotation::Vec3 = [0.0; 3];
     let mut last_sent_radius: f64 = f64::MAX;
 
-    // latency tracking
-    let mut latency_samples: Vec<f64> = Vec::with_capacity(30);
-    let mut avg_latency_ms: f64 = 0.0;
-
-    // raw values for display (set on first packet)
-    let (mut raw_yaw, mut raw_pitch, mut raw_roll): (f64, f64, f64);
-
-    // dynamic state: radius, speaker mode, and width
-    let mut current_radius: f64 = DEFAULT_RADIUS;
-    let mut speaker_mode: SpeakerMode = SpeakerMode::Front;
-    let mut reverb_enabled: bool = false; // off by default
-    let mut current_width: f64 = DEFAULT_WIDTH;
+    // presets can be saved over while running
+    let mut presets = config.presets.clone();
 
     // flag to force update when user changes settings
     let mut force_update = false;
 
+    // keys and packets arrive from their own threads, the loop sleeps until
+    // one of them or the next timer is due
+    let (tx, events) = mpsc::channel();
+    spawn_key_reader(tx.clone());
+    // slow lookups (pw-dump) run on a thread of their own too, the others
+    // are checked on a timer
+    let mut next_node_check = match backend.background_lookup() {
+        Some(lookup) => {
+            spawn_node_watcher(lookup, config.pipewire.node_name.clone(), tx.clone());
+            None
+        }
+        None => Some(Instant::now() + Duration::from_secs(NODE_CHECK_SECS)),
+    };
+    packet::spawn_reader(socket, move |result| tx.send(LoopEvent::Packet(result)).is_ok());
+
     loop {
-        // 1. handle keyboard input (non-blocking)
-        if event::poll(Duration::from_secs(0)).unwrap_or(false) {
-            if let Ok(Event::Key(key_event)) = event::read() {
-                match handle_key_event(key_event, &mut current_radius, &mut speaker_mode, &mut reverb_enabled, &mut current_width) {
-                    KeyAction::Quit => break,
+        // 1. wait for input, the rate limiter, a redraw, node checks, or a busy backend
+        let mut deadline = next_node_check.map_or(next_render, |check| check.min(next_render));
+        if pose_pending || predictor.leading() {
+            deadline = deadline.min(last_update_time + update_rate);
+        }
+        if backend.busy() {
+            deadline = deadline.min(Instant::now() + Duration::from_millis(BACKEND_POLL_MS));
+        }
+        let first = match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return Err("Input and socket readers stopped".to_string()),
+        };
+
+        // 2. handle everything that queued up; of the packets only the newest
+        //    pose matters
+        let mut packets = Vec::new();
+        for event in first.into_iter().chain(events.try_iter()) {
+            match event {
+                LoopEvent::Packet(result) => packets.push(result),
+                LoopEvent::Node(found) => {
+                    if relink(backend, &mut link, found) {
+                        // the new node starts from its conf defaults, resend everything
+                        force_update = true;
+                    }
+                }
+                LoopEvent::Key(key_event) => match handle_key_event(key_event, config, &presets, &mut settings) {
+                    KeyAction::Quit => return Ok((settings, presets)),
                     KeyAction::Changed => {
+                        notice = None;
+                        force_update = true;
+                    }
+                    KeyAction::Recenter => {
+                        recenter.capture(smoothed, last_position);
+                        notice = None;
+                        force_update = true;
+                    }
+                    KeyAction::SavePreset => {
+                        notice = Some(match save_current_preset(config, &mut presets, &mut settings) {
+                            Ok(msg) => msg,
+                            Err(e) => e,
+                        });
                         force_update = true;
                     }
                     KeyAction::None => {}
-                }
+                },
+            }
+        }
+
+        // 3. periodically search for node id if not found, and make sure
+        //    it still exists (pipewire restart, module reload) once linked;
+        //    backends with a node watcher send LoopEvent::Node instead
+        if next_node_check.is_some_and(|check| Instant::now() >= check) {
+            if check_node(backend, &mut link, &config.pipewire.node_name) {
+                force_update = true;
             }
+            next_node_check = Some(Instant::now() + Duration::from_secs(NODE_CHECK_SECS));
         }
 
-        // 2. periodically search for node id if not found
-        if cached_node_id.is_none() && last_node_search.elapsed().as_secs() > 2 {
-            cached_node_id = find_spatializer_node();
-            last_node_search = Instant::now();
+        poll_backend(backend, &mut link);
+
+        // 4. run the newest packet through the filter chain, timed by arrival
+        let lookahead = config.prediction.lookahead_ms / 1000.0;
+        if let Some(packet) = packet_stats.newest(packets) {
+            let now = Instant::now();
+            let dt = last_packet.map_or(0.0, |t| now.duration_since(t).as_secs_f64());
+            last_packet = Some(now);
+
+            raw = packet.pose;
+            smoothed = filters.apply(raw, dt, &settings.filter);
+            predicted = predictor.predict(raw, smoothed, dt, lookahead, config.prediction.max_degrees);
+            recenter.update(smoothed, dt, &config.recenter);
+            let [x, y, z] = packet.position;
+            last_position = tracker_position(x, y, z, config);
+            pose_pending = true;
+        } else if predictor.leading() {
+            // the tracker went quiet mid-turn, let the lead run out
+            let since = last_packet.map_or(f64::MAX, |t| t.elapsed().as_secs_f64());
+            predicted = predictor.fade(smoothed, since, lookahead);
+            pose_pending = true;
+        }
+
+        // 5. rate limit updates; a pending pose is picked up by the timer.
+        //    key changes go out right away, packets or not
+        let update_due = (pose_pending && last_update_time.elapsed() >= update_rate) || force_update;
+        if !update_due && Instant::now() < next_render {
+            continue;
         }
 
-        // 3. read udp packet
-        match socket.recv_from(&mut buf) {
-            Ok((48, _)) => {
-                packet_count += 1;
+        // calculate spatial positions with current radius, layout, and width
+        let head = recenter.head(predicted);
+        let listener = recenter.listener(last_position);
+        let spatial = SpatialState::from_head_tracking(
+            head,
+            listener,
+            settings.radius,
+            &config.layouts[settings.layout],
+            settings.reverb_enabled,
+            settings.width,
+            config,
+        );
+
+        // 6. send to pipewire (only if changed enough to avoid spamming, or forced)
+        if update_due && link.node.is_some() {
+            // how far the head turned, so +179 -> -179 is a small move
+            // and the center easing counts too
+            let threshold = config.smoothing.change_threshold;
+            let pose_changed = last_sent_head.is_none_or(|last| last.angle_to(head) > threshold);
+            let moved = rotation::length(rotation::sub(listener, last_sent_listener)) > POSITION_CHANGE_M;
+            let radius_changed = (settings.radius - last_sent_radius).abs() > 0.01;
+
+            if pose_changed || moved || radius_changed || force_update {
+                push_spatial(backend, &mut link, &spatial);
+
+                last_sent_head = Some(head);
+                last_sent_listener = listener;
+                last_sent_radius = settings.radius;
+            }
+        }
 
-                // parse opentrack data: [x, y, z, yaw, pitch, roll] as f64
-                let data: [f64; 6] = unsafe { std::mem::transmute(buf) };
-                raw_yaw = data[3];
-                raw_pitch = data[4];
-                raw_roll = data[5];
+        if update_due {
+            pose_pending = false;
+            force_update = false;
+            last_update_time = Instant::now();
+            frame_count += 1;
+        }
 
-                // apply smoothing
-                smoothed.update(raw_yaw, raw_pitch, raw_roll);
+        // 7. fps calculation, drops to 0 without packets
+        if last_fps_calc.elapsed() >= Duration::from_secs(1) {
+            current_fps = frame_count as f64 / last_fps_calc.elapsed().as_secs_f64();
+            frame_count = 0;
+            last_fps_calc = Instant::now();
+        }
 
-                // 4. rate limit updates
-                if last_update_time.elapsed() < Duration::from_millis(UPDATE_RATE_MS) && !force_update {
-                    continue;
-                }
+        // 8. render dashboard
+        render_dashboard(
+            &smoothed,
+            &raw,
+            &predicted,
+            &recenter.center,
+            listener,
+            &spatial,
+            current_fps,
+            &link,
+            config,
+            &packet_stats,
+            last_packet.map(|t| t.elapsed()),
+            &settings,
+            &presets,
+            notice.as_deref(),
+        );
+        stdout().flush().ok();
+
+        next_render = Instant::now() + Duration::from_millis(IDLE_RENDER_MS);
+    }
+}
 
-                // calculate spatial positions with current radius, mode, and width
-                let spatial = SpatialState::from_head_tracking(
-                    smoothed.yaw,
-                    smoothed.pitch,
-                    current_radius,
-                    speaker_mode,
-                    reverb_enabled,
-                    current_width,
-                );
-
-                // 5. send to pipewire (only if changed enough to avoid spamming, or forced)
-                if let Some(ref id) = cached_node_id {
-                    let yaw_changed = (smoothed.yaw - last_sent_yaw).abs() > CHANGE_THRESHOLD;
-                    let pitch_changed = (smoothed.pitch - last_sent_pitch).abs() > CHANGE_THRESHOLD;
-                    let radius_changed = (current_radius - last_sent_radius).abs() > 0.01;
-
-                    if yaw_changed || pitch_changed || radius_changed || force_update {
-                        let start = Instant::now();
-                        update_pipewire(id, &spatial);
-                        let cmd_latency = start.elapsed().as_secs_f64() * 1000.0;
-
-                        // track latency samples for averaging
-                        latency_samples.push(cmd_latency);
-                        if latency_samples.len() > 30 {
-                            latency_samples.remove(0);
-                        }
-                        avg_latency_ms = latency_samples.iter().sum::<f64>() / latency_samples.len() as f64;
-
-                        last_sent_yaw = smoothed.yaw;
-                        last_sent_pitch = smoothed.pitch;
-                        last_sent_radius = current_radius;
-                    }
-                }
+// what wakes the main loop
+enum LoopEvent {
+    Key(KeyEvent),
+    Packet(Result<packet::Packet, packet::PacketError>),
+    Node(Option<NodeInfo>), // a background lookup finished
+}
 
-                force_update = false;
+// looks the node up every NODE_CHECK_SECS, starting right away, so a slow
+// lookup never holds up the loop
+fn spawn_node_watcher(lookup: fn(&str) -> Option<NodeInfo>, node_name: String, tx: Sender<LoopEvent>) {
+    std::thread::spawn(move || loop {
+        if tx.send(LoopEvent::Node(lookup(&node_name))).is_err() {
+            break;
+        }
+        std::thread::sleep(Duration::from_secs(NODE_CHECK_SECS));
+    });
+}
 
-                // 6. fps calculation
-                frame_count += 1;
-                if last_fps_calc.elapsed() >= Duration::from_secs(1) {
-                    current_fps = frame_count as f64 / last_fps_calc.elapsed().as_secs_f64();
-                    frame_count = 0;
-                    last_fps_calc = Instant::now();
+// blocks on the terminal on its own thread; it's left behind on quit and ends
+// with the process
+fn spawn_key_reader(tx: Sender<LoopEvent>) {
+    std::thread::spawn(move || loop {
+        match event::read() {
+            Ok(Event::Key(key_event)) => {
+                if tx.send(LoopEvent::Key(key_event)).is_err() {
+                    break;
                 }
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    });
+}
 
-                // 7. render dashboard
-                render_dashboard(
-                    &smoothed,
-                    raw_yaw,
-                    raw_pitch,
-                    raw_roll,
-                    &spatial,
-                    current_fps,
-                    &cached_node_id,
-                    avg_latency_ms,
-                    packet_count,
-                    speaker_mode,
-                    reverb_enabled,
-                    current_width,
-                );
-                stdout().flush().ok();
-
-                last_update_time = Instant::now();
+// save the current settings into the recalled preset's slot, or the first
+// free one, both in the config file and the running list
+fn save_current_preset(
+    config: &config::Config,
+    presets: &mut Vec<config::Preset>,
+    settings: &mut Settings,
+) -> Result<String, String> {
+    let slot = settings.preset.filter(|&slot| slot < presets.len()).unwrap_or(presets.len());
+    if slot >= config::MAX_PRESETS {
+        return Err(format!("All {} preset slots are used, recall one to overwrite it", config::MAX_PRESETS));
+    }
+    let name = match presets.get(slot) {
+        Some(existing) => existing.name.clone(),
+        None => format!("preset {}", slot + 1),
+    };
+    let preset = settings.to_preset(&name, config);
+    config::save_preset(&config.path, slot, &preset)?;
+
+    if slot < presets.len() {
+        presets[slot] = preset;
+    } else {
+        presets.push(preset);
+    }
+    settings.preset = Some(slot);
+    Ok(format!("Saved preset '{}' to slot {}", name, slot + 1))
+}
+
+// ==============================================================================
+// command line
+// ==============================================================================
+
+#[derive(Clone, Copy, PartialEq)]
+enum Subcommand {
+    Run,
+    GenerateConf,
+    Install,
+    Uninstall,
+    Doctor,
+}
+
+struct Options {
+    command: Subcommand,
+    backend: Option<String>, // None = native if available, else pw-cli
+    dry_run_log: String,
+    output: Option<String>,    // None = stdout
+    sofa_path: Option<String>, // None = default for the command
+    ir_path: Option<String>,
+    restart_pipewire: bool,
+    config_path: Option<String>, // None = ~/.config/spatial-track/config.toml
+    listen: Option<SocketAddr>,  // overrides [tracker] listen
+    restore_state: bool,
+    show_help: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        command: Subcommand::Run,
+        backend: None,
+        dry_run_log: "spatial-track-dry-run.log".to_string(),
+        output: None,
+        sofa_path: None,
+        ir_path: None,
+        restart_pipewire: true,
+        config_path: None,
+        listen: None,
+        restore_state: true,
+        show_help: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => options.show_help = true,
+            "generate-conf" if options.command == Subcommand::Run => options.command = Subcommand::GenerateConf,
+            "install" if options.command == Subcommand::Run => options.command = Subcommand::Install,
+            "uninstall" if options.command == Subcommand::Run => options.command = Subcommand::Uninstall,
+            "doctor" if options.command == Subcommand::Run => options.command = Subcommand::Doctor,
+            "--no-restart" => options.restart_pipewire = false,
+            "--defaults" => options.restore_state = false,
+            "-c" | "--config" => {
+                options.config_path = Some(args.next().ok_or("--config needs a path")?);
             }
-            Ok(_) => continue, // bad packet size, skip
-            Err(e) => {
-                if e.kind() != std::io::ErrorKind::WouldBlock
-                    && e.kind() != std::io::ErrorKind::TimedOut {
-                    // don't print errors in raw mode, just continue
+            "-l" | "--listen" => {
+                let addr = args.next().ok_or("--listen needs an address")?;
+                options.listen = Some(config::parse_listen_addr(&addr)?);
+            }
+            "-o" | "--output" => {
+                options.output = Some(args.next().ok_or("--output needs a path")?);
+            }
+            "--sofa" => {
+                options.sofa_path = Some(args.next().ok_or("--sofa needs a path")?);
+            }
+            "--ir" => {
+                options.ir_path = Some(args.next().ok_or("--ir needs a path")?);
+            }
+            "--backend" => {
+                let name = args.next().ok_or("--backend needs a value")?;
+                if !matches!(name.as_str(), "pw-cli" | "pw-cli-session" | "native" | "dry-run") {
+                    return Err(format!("unknown backend '{}'", name));
                 }
-                // sleep a tiny bit to save cpu when no data
-                std::thread::sleep(Duration::from_millis(5));
+                options.backend = Some(name);
+            }
+            "--dry-run-log" => {
+                options.dry_run_log = args.next().ok_or("--dry-run-log needs a path")?;
             }
+            other => return Err(format!("unknown argument '{}'", other)),
         }
     }
+    Ok(options)
+}
 
-    Ok(())
+fn print_usage() {
+    println!("Usage: spatial-track [COMMAND] [OPTIONS]");
+    println!();
+    println!("Commands:");
+    println!("  (none)                 run the head tracking dashboard");
+    println!("  generate-conf          write the filter-chain config for pipewire.conf.d");
+    println!("  install                install the config, SOFA and reverb files for this user");
+    println!("  uninstall              remove everything install put in place");
+    println!("  doctor                 check every prerequisite and report what's missing");
+    println!();
+    println!("Options:");
+    println!("  -c, --config <PATH>    config file (default: {})", config::default_path().display());
+    println!("  -l, --listen <ADDR>    where to receive OpenTrack UDP, e.g. 0.0.0.0:4242 or [::1]:4242");
+    println!("                         (default: [tracker] listen from the config, else 127.0.0.1:4242)");
+    println!("  --defaults             start from the config defaults instead of the settings saved on last quit");
+    println!("  --backend <NAME>       pw-cli, pw-cli-session, native or dry-run (default: native if available, else pw-cli)");
+    println!("  --dry-run-log <PATH>   where the dry-run backend logs payloads (default: spatial-track-dry-run.log)");
+    println!("  -o, --output <PATH>    generate-conf: write to PATH instead of stdout");
+    println!("  --sofa <PATH>          generate-conf: SOFA path in the config (default: {})", graph::DEFAULT_SOFA_PATH);
+    println!("                         install: SOFA file to install (default: the bundled one)");
+    println!("  --ir <PATH>            generate-conf: reverb IR path in the config (default: {})", graph::DEFAULT_IR_PATH);
+    println!("                         install: impulse response to install (default: a generated room)");
+    println!("  --no-restart           install/uninstall: don't restart pipewire");
+    println!("  -h, --help             show this help");
+}
+
+fn open_backend(options: &Options) -> Result<Box<dyn OutputBackend>, String> {
+    match options.backend.as_deref() {
+        Some("pw-cli") => Ok(Box::new(backend::PwCliBackend::default())),
+        Some("pw-cli-session") => Ok(Box::new(backend::PwCliSessionBackend::new())),
+        Some("dry-run") => Ok(Box::new(backend::DryRunBackend::new(&options.dry_run_log)?)),
+        #[cfg(feature = "native")]
+        Some("native") => Ok(Box::new(native::NativeBackend::connect()?)),
+        #[cfg(not(feature = "native"))]
+        Some("native") => Err("built without the 'native' feature".to_string()),
+        _ => {
+            #[cfg(feature = "native")]
+            if let Ok(native) = native::NativeBackend::connect() {
+                return Ok(Box::new(native));
+            }
+            Ok(Box::new(backend::PwCliBackend::default()))
+        }
+    }
 }
 
 // ==============================================================================
@@ -634,16 +1335,30 @@ fn run_main_loop() -> Result<(), String> {
 enum KeyAction {
     Quit,
     Changed,
+    Recenter,
+    SavePreset,
     None,
 }
 
 fn handle_key_event(
     key: KeyEvent,
-    radius: &mut f64,
-    mode: &mut SpeakerMode,
-    reverb_enabled: &mut bool,
-    width: &mut f64,
+    config: &config::Config,
+    presets: &[config::Preset],
+    settings: &mut Settings,
 ) -> KeyAction {
+    // recalling a preset needs the whole struct, the rest only single fields
+    if let KeyCode::Char(digit @ '1'..='9') = key.code {
+        let slot = digit as usize - '1' as usize;
+        return match presets.get(slot) {
+            Some(preset) => {
+                settings.apply_preset(slot, preset, config);
+                KeyAction::Changed
+            }
+            None => KeyAction::None,
+        };
+    }
+
+    let Settings { radius, width, layout, reverb_enabled, filter, tuning, .. } = settings;
     match key.code {
         // quit keys
         KeyCode::Char('q') | KeyCode::Char('Q') | KeyCode::Esc => KeyAction::Quit,
@@ -651,48 +1366,443 @@ fn handle_key_event(
 
         // radius control: up/down arrows
         KeyCode::Up => {
-            *radius = (*radius + RADIUS_STEP).min(MAX_RADIUS);
+            *radius = (*radius + config.radius.step).min(config.radius.max);
             KeyAction::Changed
         }
         KeyCode::Down => {
-            *radius = (*radius - RADIUS_STEP).max(MIN_RADIUS);
+            *radius = (*radius - config.radius.step).max(config.radius.min);
             KeyAction::Changed
         }
 
         // width control: left/right arrows
         KeyCode::Right => {
-            *width = (*width + WIDTH_STEP).min(MAX_WIDTH);
+            *width = (*width + config.width.step).min(config.width.max);
             KeyAction::Changed
         }
         KeyCode::Left => {
-            *width = (*width - WIDTH_STEP).max(MIN_WIDTH);
+            *width = (*width - config.width.step).max(config.width.min);
             KeyAction::Changed
         }
 
-        // speaker mode: w = front, s = back
-        KeyCode::Char('w') | KeyCode::Char('W') => {
-            if *mode != SpeakerMode::Front {
-                *mode = SpeakerMode::Front;
+        // layouts: l = next, w/s = the front/back layouts if they're defined
+        KeyCode::Char('l') | KeyCode::Char('L') => {
+            if config.layouts.len() > 1 {
+                *layout = (*layout + 1) % config.layouts.len();
                 KeyAction::Changed
             } else {
                 KeyAction::None
             }
         }
-        KeyCode::Char('s') | KeyCode::Char('S') => {
-            if *mode != SpeakerMode::Back {
-                *mode = SpeakerMode::Back;
+        KeyCode::Char('w') | KeyCode::Char('W') => select_layout(config, layout, "front"),
+        KeyCode::Char('s') | KeyCode::Char('S') => select_layout(config, layout, "back"),
+
+        // reverb toggle: r key
+        KeyCode::Char('r') | KeyCode::Char('R') => {
+            *reverb_enabled = !*reverb_enabled;
+            KeyAction::Changed
+        }
+
+        // filter tuning: f picks the next parameter of the chain, [ and ] change it
+        KeyCode::Char('f') | KeyCode::Char('F') => {
+            let count = filter::tunables(&config.smoothing.filters).len();
+            if count > 1 {
+                *tuning = (*tuning + 1) % count;
                 KeyAction::Changed
             } else {
                 KeyAction::None
             }
         }
+        KeyCode::Char(c @ ('[' | ']')) => match filter::tunables(&config.smoothing.filters).get(*tuning) {
+            Some((_, param)) => {
+                param.adjust(filter, c == ']');
+                KeyAction::Changed
+            }
+            None => KeyAction::None,
+        },
 
-        // reverb toggle: r key
-        KeyCode::Char('r') | KeyCode::Char('R') => {
-            *reverb_enabled = !*reverb_enabled;
+        // the current orientation becomes straight ahead: c key
+        KeyCode::Char('c') | KeyCode::Char('C') => KeyAction::Recenter,
+
+        // save into the current preset slot: p key
+        KeyCode::Char('p') | KeyCode::Char('P') => KeyAction::SavePreset,
+
+        _ => KeyAction::None,
+    }
+}
+
+fn select_layout(config: &config::Config, layout: &mut usize, name: &str) -> KeyAction {
+    match layout_index(config, name) {
+        Some(index) if index != *layout => {
+            *layout = index;
             KeyAction::Changed
         }
-
         _ => KeyAction::None,
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::MockBackend;
+
+    const NODE_NAME: &str = "effect_input.spatializer";
+
+    fn head(yaw: f64, pitch: f64) -> rotation::Quat {
+        rotation::Quat::from_yaw_pitch_roll(yaw, pitch, 0.0)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    fn test_spatial() -> SpatialState {
+        let config = config::Config::default();
+        SpatialState::from_head_tracking(head(10.0, 5.0), [0.0; 3], config.radius.default, &config.layouts[0], true, config.width.default, &config)
+    }
+
+    #[test]
+    fn push_spatial_sends_every_control_to_linked_node() {
+        let mut mock = MockBackend::default();
+        let mut link = LinkStatus::new(mock.name(), graph::STEREO);
+        link.link(NodeInfo::new("42", NODE_NAME));
+
+        push_spatial(&mut mock, &mut link, &test_spatial());
+
+        assert_eq!(mock.pushes.len(), 1);
+        let (id, params) = &mock.pushes[0];
+        assert_eq!(id, "42");
+        assert!(params.iter().any(|(name, _)| name == "spat_left:Azimuth"));
+        assert!(params.iter().any(|(name, _)| name == "final_mix_r:Gain 2"));
+        assert_eq!(link.push_errors, 0);
+    }
+
+    #[test]
+    fn push_spatial_skips_unlinked_node() {
+        let mut mock = MockBackend::default();
+        let mut link = LinkStatus::new(mock.name(), graph::STEREO);
+
+        push_spatial(&mut mock, &mut link, &test_spatial());
+
+        assert!(mock.pushes.is_empty());
+    }
+
+    #[test]
+    fn failed_push_is_counted() {
+        let mut mock = MockBackend { fail_pushes: true, ..Default::default() };
+        let mut link = LinkStatus::new(mock.name(), graph::STEREO);
+        link.link(NodeInfo::new("42", NODE_NAME));
+
+        push_spatial(&mut mock, &mut link, &test_spatial());
+
+        assert_eq!(link.push_errors, 1);
+        assert!(link.last_error.is_some());
+    }
+
+    #[test]
+    fn repeated_failures_drop_link_and_relink_counts_reconnect() {
+        let mut mock = MockBackend { fail_pushes: true, ..Default::default() };
+        let mut link = LinkStatus::new(mock.name(), graph::STEREO);
+        link.link(NodeInfo::new("42", NODE_NAME));
+
+        for _ in 0..MAX_PUSH_FAILURES {
+            push_spatial(&mut mock, &mut link, &test_spatial());
+        }
+        assert!(link.node.is_none());
+
+        mock.node_id = Some("43".to_string());
+        assert!(check_node(&mut mock, &mut link, NODE_NAME));
+        assert_eq!(link.node.as_ref().map(|node| node.id.as_str()), Some("43"));
+        assert_eq!(link.reconnects, 1);
+    }
+
+    #[test]
+    fn node_check_follows_the_node_through_restarts() {
+        let mut mock = MockBackend::default();
+        let mut link = LinkStatus::new(mock.name(), graph::STEREO);
+
+        // nothing there yet: stays SEARCHING
+        assert!(!check_node(&mut mock, &mut link, NODE_NAME));
+        assert!(link.node.is_none());
+
+        mock.node_id = Some("42".to_string());
+        assert!(check_node(&mut mock, &mut link, NODE_NAME));
+        // same id again: nothing to resend
+        assert!(!check_node(&mut mock, &mut link, NODE_NAME));
+        assert_eq!(link.reconnects, 0);
+
+        // pipewire restarted: the node vanishes, then comes back under a new id
+        mock.node_id = None;
+        assert!(!check_node(&mut mock, &mut link, NODE_NAME));
+        assert!(link.node.is_none());
+        assert_eq!(link.last_error.as_deref(), Some("node disappeared"));
+
+        mock.node_id = Some("57".to_string());
+        assert!(check_node(&mut mock, &mut link, NODE_NAME));
+        assert_eq!(link.node.as_ref().map(|node| node.id.as_str()), Some("57"));
+        assert_eq!(link.reconnects, 1);
+
+        // an id change without a gap in between is a reconnect too
+        mock.node_id = Some("58".to_string());
+        assert!(check_node(&mut mock, &mut link, NODE_NAME));
+        assert_eq!(link.reconnects, 2);
+        assert_eq!(mock.lookups, vec![NODE_NAME; 6]);
+    }
+
+    #[test]
+    fn node_watcher_reports_lookups_to_the_loop() {
+        fn lookup(node_name: &str) -> Option<NodeInfo> {
+            Some(NodeInfo::new("42", node_name))
+        }
+        let (tx, events) = mpsc::channel();
+        spawn_node_watcher(lookup, NODE_NAME.to_string(), tx);
+
+        // the first lookup doesn't wait for NODE_CHECK_SECS
+        let Ok(LoopEvent::Node(found)) = events.recv_timeout(Duration::from_secs(1)) else { panic!("no lookup") };
+        let mut mock = MockBackend::default();
+        let mut link = LinkStatus::new(mock.name(), graph::STEREO);
+        assert!(relink(&mut mock, &mut link, found));
+        assert_eq!(link.node.as_ref().map(|node| node.id.as_str()), Some("42"));
+        assert!(mock.lookups.is_empty());
+    }
+
+    #[test]
+    fn queued_pushes_count_once_acknowledged() {
+        let mut mock = MockBackend { queue_pushes: true, ..Default::default() };
+        let mut link = LinkStatus::new(mock.name(), graph::STEREO);
+        link.link(NodeInfo::new("42", NODE_NAME));
+
+        // queued is neither a success nor a failure yet
+        push_spatial(&mut mock, &mut link, &test_spatial());
+        assert!(link.last_success.is_none());
+        assert_eq!(link.push_errors, 0);
+
+        mock.polls = vec![Ok(None), Ok(Some(12.0))];
+        poll_backend(&mut mock, &mut link);
+        assert!(link.last_success.is_none());
+        poll_backend(&mut mock, &mut link);
+        assert!(link.last_success.is_some());
+        assert_eq!(link.latency_ms, 12.0);
+
+        // errors reported later aren't wiped out by the next queued push
+        for _ in 0..MAX_PUSH_FAILURES {
+            mock.polls = vec![Err("pw-cli exited".to_string())];
+            poll_backend(&mut mock, &mut link);
+            push_spatial(&mut mock, &mut link, &test_spatial());
+        }
+        assert_eq!(link.push_errors, MAX_PUSH_FAILURES as u64);
+        assert!(link.node.is_none());
+        assert_eq!(link.last_error.as_deref(), Some("link lost: pw-cli exited"));
+    }
+
+    #[test]
+    fn push_spatial_only_sends_controls_the_graph_has() {
+        let mut mock = MockBackend::default();
+        let mut link = LinkStatus::new(mock.name(), graph::STEREO);
+        let mut node = NodeInfo::new("42", NODE_NAME);
+        node.controls = vec!["spat_left:Azimuth".to_string(), "spat_right:Azimuth".to_string()];
+        link.link(node);
+
+        push_spatial(&mut mock, &mut link, &test_spatial());
+
+        let names: Vec<&str> = mock.pushes[0].1.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["spat_left:Azimuth", "spat_right:Azimuth"]);
+        assert_eq!(link.warnings.len(), 3);
+    }
+
+    #[test]
+    fn layouts_are_heard_where_they_say_and_sent_mirrored() {
+        let config = config::parse(
+            "[[layouts]]\nname = \"studio\"\nspeakers = [{ channel = \"FL\", azimuth = 30.0 }, { channel = \"FR\", azimuth = -30.0, gain = 0.5 }]",
+        )
+        .unwrap();
+        let param = |spatial: &SpatialState, name: &str| {
+            build_params(spatial).into_iter().find(|(n, _)| n == name).map(|(_, v)| v).unwrap()
+        };
+
+        // the default front layout keeps sending what the old front mode did
+        let front = SpatialState::from_head_tracking(head(0.0, 0.0), [0.0; 3], 1.0, &config.layouts[0], false, 1.0, &config);
+        assert_close(param(&front, "spat_left:Azimuth"), 135.0);
+        assert_close(param(&front, "spat_right:Azimuth"), -135.0);
+        // ...while the generated conf labels them where they're heard
+        let conf = graph::generate_conf(&startup_graph(&config, graph::DEFAULT_SOFA_PATH, graph::DEFAULT_IR_PATH));
+        assert!(conf.contains("# --- Left Virtual Speaker (heard at +45 deg) ---"));
+        assert!(conf.contains("\"Azimuth\" = 135.0"));
+
+        let studio = &config.layouts[2];
+        let spatial = SpatialState::from_head_tracking(head(20.0, 0.0), [0.0; 3], 1.0, studio, false, 1.0, &config);
+        assert_close(spatial.speaker("FL").unwrap().azimuth, 50.0);
+        assert_close(spatial.speaker("FR").unwrap().azimuth, -10.0);
+        // FR's gain goes through its input on both ear mixers
+        assert_close(param(&spatial, "mix_l:Gain 2"), 0.5 * 0.5);
+        assert_close(param(&spatial, "mix_r:Gain 2"), 0.5 * 0.5);
+
+        // turning far enough wraps round behind the head
+        let spatial = SpatialState::from_head_tracking(head(170.0, 0.0), [0.0; 3], 1.0, studio, false, 1.0, &config);
+        assert_close(spatial.speaker("FL").unwrap().azimuth, -160.0);
+    }
+
+    #[test]
+    fn surround_graph_spatializes_every_channel_but_lfe() {
+        let config = config::parse("[pipewire]\nchannels = \"5.1\"").unwrap();
+        let spatial = SpatialState::from_head_tracking(head(30.0, 0.0), [0.0; 3], 1.0, &config.layouts[0], false, 1.0, &config);
+        let channels: Vec<&str> = spatial.speakers.iter().map(|s| s.channel).collect();
+        assert_eq!(channels, vec!["FL", "FR", "FC", "RL", "RR"]);
+        // channels the stereo layout doesn't place sit at ITU angles and turn with the head
+        assert_close(spatial.speaker("FC").unwrap().azimuth, 30.0);
+        assert_close(spatial.speaker("RR").unwrap().azimuth, -80.0);
+
+        let conf = graph::generate_conf(&startup_graph(&config, graph::DEFAULT_SOFA_PATH, graph::DEFAULT_IR_PATH));
+        assert!(conf.contains("audio.position = [ FL FR FC LFE RL RR ]"));
+        assert!(conf.contains("\"lfe:In\""));
+        for (control, _) in build_params(&spatial) {
+            let node = control.split(':').next().unwrap();
+            assert!(conf.contains(&format!("name   = {}\n", node)), "{} missing from conf", node);
+        }
+    }
+
+    #[test]
+    fn pitch_moves_height_and_floor_speakers_differently() {
+        let config = config::parse("[pipewire]\nchannels = \"7.1.4\"").unwrap();
+        let layout = &config.layouts[0];
+        let at = |yaw, pitch, channel: &str| {
+            let spatial = SpatialState::from_head_tracking(head(yaw, pitch), [0.0; 3], 1.0, layout, false, 1.0, &config);
+            let speaker = spatial.speaker(channel).unwrap();
+            (speaker.azimuth, speaker.elevation)
+        };
+
+        // at rest every speaker keeps its own elevation
+        assert_close(at(0.0, 0.0, "TFL").1, 45.0);
+        assert_close(at(0.0, 0.0, "FC").1, 0.0);
+
+        // looking up drops what's ahead, lifts what's behind, leaves the sides level
+        assert_close(at(0.0, 20.0, "FC").1, -20.0);
+        // a front-left height speaker is only partly ahead, so it drops less
+        let tfl = at(0.0, 20.0, "TFL").1;
+        assert!(tfl > 25.0 && tfl < 45.0, "{}", tfl);
+        assert!(at(0.0, 20.0, "TRL").1 > 45.0);
+        assert_close(at(0.0, 20.0, "SL").0, 90.0);
+        assert_close(at(0.0, 20.0, "SL").1, 0.0);
+
+        // turning leaves elevations alone
+        assert_close(at(60.0, 0.0, "TRR").0, -75.0);
+        assert_close(at(60.0, 0.0, "TRR").1, 45.0);
+    }
+
+    #[test]
+    fn leaning_moves_each_speaker_by_its_own_parallax() {
+        let config = config::Config::default();
+        let front = &config.layouts[0];
+        let centered = SpatialState::from_head_tracking(head(0.0, 0.0), [0.0; 3], 1.5, front, false, 1.0, &config);
+        // half a metre towards the left speaker's side
+        let leaning = SpatialState::from_head_tracking(head(0.0, 0.0), [0.0, 0.5, 0.0], 1.5, front, false, 1.0, &config);
+
+        let (left, right) = (leaning.speaker("FL").unwrap(), leaning.speaker("FR").unwrap());
+        assert!(left.distance < 1.5 && right.distance > 1.5);
+        assert!(left.gain > centered.speaker("FL").unwrap().gain);
+        // the left one swings towards the front, the right one further out
+        assert!(left.azimuth < 45.0 && left.azimuth > 0.0);
+        assert!(right.azimuth < -45.0);
+
+        let params = build_params(&leaning);
+        let radius = |name: &str| params.iter().find(|(n, _)| n == name).unwrap().1;
+        assert_close(radius("spat_left:Radius"), left.distance);
+        assert!(radius("spat_left:Radius") < radius("spat_right:Radius"));
+
+        // opentrack centimetres, leaning left is -x
+        let config = config::Config {
+            translation: config::TranslationConfig { enabled: true, scale: 0.01 },
+            ..Default::default()
+        };
+        assert_eq!(tracker_position(-50.0, 0.0, 0.0, &config), [0.0, 0.5, 0.0]);
+        assert_eq!(tracker_position(-50.0, 0.0, 0.0, &config::Config::default()), [0.0; 3]);
+    }
+
+    #[test]
+    fn silent_tracker_is_reported_with_the_time_since_its_last_packet() {
+        let listen: SocketAddr = "127.0.0.1:4242".parse().unwrap();
+        assert_eq!(no_tracker_message(None, listen).unwrap(), "waiting for OpenTrack on 127.0.0.1:4242");
+        assert_eq!(no_tracker_message(Some(Duration::from_millis(20)), listen), None);
+        assert_eq!(no_tracker_message(Some(Duration::from_millis(12_340)), listen).unwrap(), "last packet 12.3s ago");
+    }
+
+    #[test]
+    fn notices_wrap_at_spaces() {
+        let lines = wrap_words("Invalid state file /home/me/.local/state/spatial-track/state.toml: TOML parse error", 30);
+        assert_eq!(lines, vec!["Invalid state file", "/home/me/.local/state/spatial-", "track/state.toml: TOML parse", "error"]);
+        assert!(wrap_words("", 30).is_empty());
+    }
+
+    #[test]
+    fn truncating_errors_keeps_whole_characters() {
+        let err = format!("{}é ünknown", "x".repeat(49));
+        assert_eq!(truncate_chars(&err, 50), format!("{}é", "x".repeat(49)));
+        assert_eq!(truncate_chars("short", 50), "short");
+    }
+
+    #[test]
+    fn number_keys_recall_presets() {
+        let config = config::Config::default();
+        let presets = vec![config::Preset {
+            name: "movie".to_string(),
+            radius: Some(3.0),
+            layout: Some("back".to_string()),
+            ..Default::default()
+        }];
+        let mut settings = Settings::defaults(&config);
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        assert!(matches!(handle_key_event(key('2'), &config, &presets, &mut settings), KeyAction::None));
+        assert!(matches!(handle_key_event(key('1'), &config, &presets, &mut settings), KeyAction::Changed));
+        assert_eq!(settings.radius, 3.0);
+        assert_eq!(settings.layout, 1);
+        assert_eq!(settings.width, config.width.default);
+        assert_eq!(settings.preset, Some(0));
+        assert!(settings.matches(&presets[0], &config));
+
+        handle_key_event(KeyEvent::new(KeyCode::Up, KeyModifiers::NONE), &config, &presets, &mut settings);
+        assert!(!settings.matches(&presets[0], &config));
+    }
+
+    #[test]
+    fn filter_keys_tune_the_chain_in_order() {
+        let mut config = config::Config::default();
+        config.smoothing.filters = vec![filter::FilterKind::OneEuro, filter::FilterKind::Ema];
+        let mut settings = Settings::defaults(&config);
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        // starts on the first filter's first parameter
+        handle_key_event(key(']'), &config, &[], &mut settings);
+        assert!((settings.filter.min_cutoff - 1.1).abs() < 1e-9);
+
+        // beta, then the ema factor, then round again
+        handle_key_event(key('f'), &config, &[], &mut settings);
+        handle_key_event(key('f'), &config, &[], &mut settings);
+        handle_key_event(key('['), &config, &[], &mut settings);
+        assert!((settings.filter.factor - 0.6).abs() < 1e-9);
+        assert_eq!(settings.filter.beta, config.smoothing.one_euro.beta);
+        handle_key_event(key('f'), &config, &[], &mut settings);
+        assert_eq!(settings.tuning, 0);
+    }
+
+    #[test]
+    fn generated_conf_has_every_control_we_push_to() {
+        // the control list of one node's block in the conf
+        fn declared(conf: &str, node: &str) -> Option<String> {
+            let block = &conf[conf.find(&format!("name   = {}\n", node))?..];
+            let block = &block[..block.find("\n                    }")?];
+            Some(block.lines().find(|line| line.trim_start().starts_with("control = "))?.to_string())
+        }
+
+        for format in graph::FORMATS {
+            let config = config::parse(&format!("[pipewire]\nchannels = \"{}\"", format.name)).unwrap();
+            let conf = graph::generate_conf(&startup_graph(&config, graph::DEFAULT_SOFA_PATH, graph::DEFAULT_IR_PATH));
+            let spatial = SpatialState::from_head_tracking(head(10.0, 5.0), [0.0; 3], 1.0, &config.layouts[0], true, 1.0, &config);
+            for (control, _) in build_params(&spatial) {
+                let (node, name) = control.split_once(':').unwrap();
+                let controls = declared(&conf, node).unwrap_or_else(|| panic!("{}: {} missing from conf", format.name, node));
+                assert!(controls.contains(&format!("\"{}\" =", name)), "{}: {} has no {}", format.name, node, name);
+            }
+            assert!(conf.contains(&format!("node.name      = \"{}\"", NODE_NAME)));
+        }
+    }
+}
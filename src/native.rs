@@ -0,0 +1,348 @@
+// ==============================================================================
+// NATIVE PIPEWIRE CONTROL
+// ==============================================================================
+//
+// keeps a single connection to the pipewire daemon open and sets Props on the
+// filter-chain node directly, instead of spawning pw-cli for every update.
+// every push is followed by a core sync so we get a real round-trip time and
+// see any error the server reports for the node. the main loop doesn't wait
+// for it: poll picks the answer up, and like the pw-cli session only one push
+// is in flight, with newer ones replacing the queued one.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use pipewire as pw;
+use pw::spa::param::ParamType;
+use pw::spa::pod::deserialize::PodDeserializer;
+use pw::spa::pod::serialize::PodSerializer;
+use pw::spa::pod::{Object, Pod, Property, PropertyFlags, Value};
+use pw::spa::utils::SpaTypes;
+
+use crate::backend::{self, NodeInfo, OutputBackend, Pushed};
+
+// how long to wait for the server to acknowledge a push or a read
+const ROUNDTRIP_TIMEOUT: Duration = Duration::from_millis(200);
+
+pub struct NativeBackend {
+    mainloop: pw::main_loop::MainLoop,
+    _context: pw::context::Context,
+    core: pw::core::Core,
+    registry: pw::registry::Registry,
+    _core_listener: pw::core::Listener,
+    _registry_listener: pw::registry::Listener,
+
+    // global id -> node props, kept up to date by the registry listener
+    nodes: Rc<RefCell<HashMap<u32, NodeProps>>>,
+    // last sequence number acknowledged by the server
+    done_seq: Rc<Cell<i32>>,
+    // last error reported by the server (cleared on every push)
+    last_error: Rc<RefCell<Option<String>>>,
+
+    bound_node: Option<BoundNode>,
+
+    pending: Option<(u32, Vec<(String, f64)>)>,
+    in_flight: Option<(i32, Instant)>, // sync seq sent after the push
+    acked: Option<f64>,                // round-trip time poll hasn't reported yet
+}
+
+// the registry props we care about for discovery
+struct NodeProps {
+    name: String,
+    media_class: Option<String>,
+    link_group: Option<String>,
+}
+
+// a node proxy plus the listener collecting its Props for read-back
+struct BoundNode {
+    id: u32,
+    node: pw::node::Node,
+    _listener: pw::node::NodeListener,
+    props: Rc<RefCell<Vec<(String, f64)>>>,
+}
+
+impl NativeBackend {
+    pub fn connect() -> Result<Self, String> {
+        pw::init();
+
+        let mainloop = pw::main_loop::MainLoop::new(None)
+            .map_err(|e| format!("Failed to create pipewire main loop: {}", e))?;
+        let context = pw::context::Context::new(&mainloop)
+            .map_err(|e| format!("Failed to create pipewire context: {}", e))?;
+        let core = context
+            .connect(None)
+            .map_err(|e| format!("Failed to connect to pipewire: {}", e))?;
+        let registry = core
+            .get_registry()
+            .map_err(|e| format!("Failed to get pipewire registry: {}", e))?;
+
+        let nodes: Rc<RefCell<HashMap<u32, NodeProps>>> = Rc::new(RefCell::new(HashMap::new()));
+        let done_seq = Rc::new(Cell::new(-1));
+        let last_error: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
+
+        let done_clone = done_seq.clone();
+        let error_clone = last_error.clone();
+        let core_listener = core
+            .add_listener_local()
+            .done(move |id, seq| {
+                if id == pw::core::PW_ID_CORE {
+                    done_clone.set(seq.seq());
+                }
+            })
+            .error(move |id, _seq, res, message| {
+                *error_clone.borrow_mut() = Some(format!("object {}: {} ({})", id, message, res));
+            })
+            .register();
+
+        let nodes_add = nodes.clone();
+        let nodes_remove = nodes.clone();
+        let registry_listener = registry
+            .add_listener_local()
+            .global(move |global| {
+                if global.type_ != pw::types::ObjectType::Node {
+                    return;
+                }
+                let Some(props) = global.props else { return };
+                if let Some(name) = props.get("node.name") {
+                    nodes_add.borrow_mut().insert(
+                        global.id,
+                        NodeProps {
+                            name: name.to_string(),
+                            media_class: props.get("media.class").map(str::to_string),
+                            link_group: props.get("node.link-group").map(str::to_string),
+                        },
+                    );
+                }
+            })
+            .global_remove(move |id| {
+                nodes_remove.borrow_mut().remove(&id);
+            })
+            .register();
+
+        let control = Self {
+            mainloop,
+            _context: context,
+            core,
+            registry,
+            _core_listener: core_listener,
+            _registry_listener: registry_listener,
+            nodes,
+            done_seq,
+            last_error,
+            bound_node: None,
+            pending: None,
+            in_flight: None,
+            acked: None,
+        };
+
+        // populate the node list before the first lookup
+        control.roundtrip()?;
+        Ok(control)
+    }
+
+    fn bind_node(&mut self, id: u32) -> Result<&BoundNode, String> {
+        if !self.nodes.borrow().contains_key(&id) {
+            self.bound_node = None;
+            return Err(format!("node {} no longer exists", id));
+        }
+
+        if self.bound_node.as_ref().map(|bound| bound.id) != Some(id) {
+            let global = pw::registry::GlobalObject::<&pw::spa::utils::dict::DictRef> {
+                id,
+                permissions: pw::permissions::PermissionFlags::all(),
+                type_: pw::types::ObjectType::Node,
+                version: 0,
+                props: None,
+            };
+            let node: pw::node::Node = self
+                .registry
+                .bind(&global)
+                .map_err(|e| format!("Failed to bind node {}: {}", id, e))?;
+
+            let props: Rc<RefCell<Vec<(String, f64)>>> = Rc::new(RefCell::new(Vec::new()));
+            let props_clone = props.clone();
+            let listener = node
+                .add_listener_local()
+                .param(move |_seq, id, _index, _next, pod| {
+                    if id != ParamType::Props {
+                        return;
+                    }
+                    if let Some(pod) = pod {
+                        props_clone.borrow_mut().extend(parse_props_pod(pod));
+                    }
+                })
+                .register();
+
+            self.bound_node = Some(BoundNode { id, node, _listener: listener, props });
+        }
+
+        self.bound_node.as_ref().ok_or_else(|| format!("Failed to bind node {}", id))
+    }
+
+    // handle whatever the server sent, then send the queued push once the
+    // previous one is acknowledged. never blocks
+    fn pump(&mut self) -> Result<(), String> {
+        self.mainloop.loop_().iterate(Duration::ZERO);
+
+        if let Some((seq, sent_at)) = self.in_flight {
+            if let Some(err) = self.last_error.borrow_mut().take() {
+                self.in_flight = None;
+                return Err(err);
+            }
+            // a read_params roundtrip in between moves done_seq past ours
+            if self.done_seq.get() >= seq {
+                self.acked = Some(sent_at.elapsed().as_secs_f64() * 1000.0);
+                self.in_flight = None;
+            } else if sent_at.elapsed() > ROUNDTRIP_TIMEOUT {
+                self.in_flight = None;
+                return Err("pipewire did not answer in time".to_string());
+            } else {
+                return Ok(());
+            }
+        }
+
+        let Some((id, params)) = self.pending.take() else { return Ok(()) };
+        let bytes = build_props_pod(&params)?;
+        let pod = Pod::from_bytes(&bytes).ok_or("Failed to build Props pod")?;
+
+        *self.last_error.borrow_mut() = None;
+        self.bind_node(id)?.node.set_param(ParamType::Props, 0, pod);
+        let seq = self
+            .core
+            .sync(0)
+            .map_err(|e| format!("pipewire sync failed: {}", e))?
+            .seq();
+        self.in_flight = Some((seq, Instant::now()));
+        Ok(())
+    }
+
+    // wait until the server has processed everything we sent so far
+    fn roundtrip(&self) -> Result<Duration, String> {
+        let start = Instant::now();
+        let pending = self
+            .core
+            .sync(0)
+            .map_err(|e| format!("pipewire sync failed: {}", e))?
+            .seq();
+
+        while self.done_seq.get() != pending {
+            if start.elapsed() > ROUNDTRIP_TIMEOUT {
+                return Err("pipewire did not answer in time".to_string());
+            }
+            self.mainloop.loop_().iterate(Duration::from_millis(5));
+        }
+        Ok(start.elapsed())
+    }
+}
+
+impl OutputBackend for NativeBackend {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn find_node(&mut self, node_name: &str) -> Option<NodeInfo> {
+        // pick up any registry changes since the last call
+        self.mainloop.loop_().iterate(Duration::ZERO);
+
+        let nodes = self.nodes.borrow();
+        let (id, props) = nodes.iter().find(|(_, props)| props.name == node_name)?;
+
+        let playback = backend::link_group_peer(
+            &id.to_string(),
+            props.link_group.as_deref(),
+            nodes.iter().map(|(other, p)| (other.to_string(), p.name.as_str(), p.link_group.as_deref())),
+        );
+
+        Some(NodeInfo {
+            id: id.to_string(),
+            name: props.name.clone(),
+            media_class: props.media_class.clone(),
+            playback,
+            controls: Vec::new(),
+        })
+    }
+
+    // only queues the push, poll reports the server's answer. an error here
+    // can belong to an earlier push
+    fn set_params(&mut self, id: &str, params: &[(String, f64)]) -> Result<Pushed, String> {
+        let id: u32 = id.parse().map_err(|_| format!("invalid node id '{}'", id))?;
+        self.pending = Some((id, params.to_vec()));
+        self.pump()?;
+        Ok(Pushed::Queued)
+    }
+
+    fn read_params(&mut self, id: &str) -> Result<Vec<(String, f64)>, String> {
+        let id: u32 = id.parse().map_err(|_| format!("invalid node id '{}'", id))?;
+
+        let bound = self.bind_node(id)?;
+        bound.props.borrow_mut().clear();
+        bound.node.enum_params(0, Some(ParamType::Props), 0, u32::MAX);
+
+        self.roundtrip()?;
+        let props = self.bound_node.as_ref().map(|b| b.props.borrow().clone()).unwrap_or_default();
+        Ok(props)
+    }
+
+    fn poll(&mut self) -> Result<Option<f64>, String> {
+        self.pump()?;
+        Ok(self.acked.take())
+    }
+
+    fn busy(&self) -> bool {
+        self.pending.is_some() || self.acked.is_some() || self.in_flight.is_some()
+    }
+}
+
+// Props object with a single 'params' struct of alternating name/value pairs,
+// the same shape pw-cli builds from the json payload
+fn build_props_pod(params: &[(String, f64)]) -> Result<Vec<u8>, String> {
+    let mut fields = Vec::with_capacity(params.len() * 2);
+    for (name, value) in params {
+        fields.push(Value::String(name.clone()));
+        fields.push(Value::Float(*value as f32));
+    }
+
+    let props = Value::Object(Object {
+        type_: SpaTypes::ObjectParamProps.as_raw(),
+        id: ParamType::Props.as_raw(),
+        properties: vec![Property {
+            key: pw::spa::sys::SPA_PROP_params,
+            flags: PropertyFlags::empty(),
+            value: Value::Struct(fields),
+        }],
+    });
+
+    PodSerializer::serialize(Cursor::new(Vec::new()), &props)
+        .map(|(cursor, _)| cursor.into_inner())
+        .map_err(|e| format!("Failed to serialize Props pod: {:?}", e))
+}
+
+// inverse of build_props_pod: pull the name/value pairs out of 'params'
+fn parse_props_pod(pod: &Pod) -> Vec<(String, f64)> {
+    let mut params = Vec::new();
+    let Ok((_, Value::Object(object))) = PodDeserializer::deserialize_any_from(pod.as_bytes()) else {
+        return params;
+    };
+
+    for prop in object.properties {
+        if prop.key != pw::spa::sys::SPA_PROP_params {
+            continue;
+        }
+        let Value::Struct(fields) = prop.value else { continue };
+        for pair in fields.chunks(2) {
+            let value = match pair.get(1) {
+                Some(Value::Float(v)) => *v as f64,
+                Some(Value::Double(v)) => *v,
+                Some(Value::Int(v)) => *v as f64,
+                _ => continue,
+            };
+            if let Value::String(name) = &pair[0] {
+                params.push((name.clone(), value));
+            }
+        }
+    }
+    params
+}
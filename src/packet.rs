@@ -0,0 +1,196 @@
+// ==============================================================================
+// OPENTRACK PACKETS
+// ==============================================================================
+//
+// opentrack's 'UDP over network' output: six little-endian f64, x, y, z
+// (tracker units, centimetres by default) then yaw, pitch, roll (degrees).
+// anything else is counted and dropped before it reaches the filters, so a
+// NaN or a garbage datagram can't end up in pipewire.
+
+use std::net::UdpSocket;
+
+use crate::filter::Pose;
+
+pub const PACKET_SIZE: usize = 48;
+
+// opentrack keeps angles within +-180; past a full turn it's garbage
+const MAX_ANGLE: f64 = 360.0;
+
+// 10 m in centimetres, well past any tracking volume
+const MAX_TRANSLATION: f64 = 1000.0;
+
+const FIELDS: [&str; 6] = ["x", "y", "z", "yaw", "pitch", "roll"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Packet {
+    pub position: [f64; 3], // x right, y up, z back, tracker units
+    pub pose: Pose,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PacketError {
+    Short(usize),
+    Malformed(String),
+}
+
+impl std::fmt::Display for PacketError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PacketError::Short(len) => write!(f, "short packet ({} of {} bytes)", len, PACKET_SIZE),
+            PacketError::Malformed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+pub fn parse(buf: &[u8]) -> Result<Packet, PacketError> {
+    if buf.len() < PACKET_SIZE {
+        return Err(PacketError::Short(buf.len()));
+    }
+    if buf.len() > PACKET_SIZE {
+        return Err(PacketError::Malformed(format!("oversized packet ({} bytes)", buf.len())));
+    }
+
+    let mut values = [0.0; 6];
+    for (i, (value, bytes)) in values.iter_mut().zip(buf.chunks_exact(8)).enumerate() {
+        *value = f64::from_le_bytes(bytes.try_into().unwrap());
+        let limit = if i < 3 { MAX_TRANSLATION } else { MAX_ANGLE };
+        if !value.is_finite() {
+            return Err(PacketError::Malformed(format!("{} is {}", FIELDS[i], value)));
+        }
+        if value.abs() > limit {
+            return Err(PacketError::Malformed(format!("{} out of range ({:.0})", FIELDS[i], value)));
+        }
+    }
+
+    let [x, y, z, yaw, pitch, roll] = values;
+    Ok(Packet { position: [x, y, z], pose: Pose::new(yaw, pitch, roll) })
+}
+
+// what came in on the socket, for the stats panel
+#[derive(Default)]
+pub struct PacketStats {
+    pub received: u64, // valid packets
+    pub short: u64,
+    pub malformed: u64,
+    pub stale: u64, // valid but a newer one was already queued
+    pub last_error: Option<String>,
+}
+
+impl PacketStats {
+    pub fn record_error(&mut self, error: PacketError) {
+        match error {
+            PacketError::Short(_) => self.short += 1,
+            PacketError::Malformed(_) => self.malformed += 1,
+        }
+        self.last_error = Some(error.to_string());
+    }
+
+    // the newest valid packet of a batch that queued up while the loop was
+    // busy; the older ones would only make the audio lag behind
+    pub fn newest(&mut self, batch: Vec<Result<Packet, PacketError>>) -> Option<Packet> {
+        let mut newest = None;
+        for result in batch {
+            match result {
+                Ok(packet) => {
+                    if newest.replace(packet).is_some() {
+                        self.stale += 1;
+                    }
+                    self.received += 1;
+                }
+                Err(e) => self.record_error(e),
+            }
+        }
+        newest
+    }
+}
+
+// blocking reads on a thread of their own, every datagram parsed and handed
+// to `send` until it returns false
+pub fn spawn_reader(socket: UdpSocket, mut send: impl FnMut(Result<Packet, PacketError>) -> bool + Send + 'static) {
+    std::thread::spawn(move || {
+        // one byte spare, so an oversized datagram shows up as one instead of
+        // being truncated to a valid-looking packet
+        let mut buf = [0u8; PACKET_SIZE + 1];
+        loop {
+            match socket.recv_from(&mut buf) {
+                Ok((len, _)) => {
+                    if !send(parse(&buf[..len])) {
+                        break;
+                    }
+                }
+                // e.g. an icmp error from an earlier send; don't spin on it
+                Err(_) => std::thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(values: [f64; 6]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn decodes_little_endian_fields_in_order() {
+        let packet = parse(&encode([1.5, -2.0, 3.25, 170.0, -20.0, 5.5])).unwrap();
+        assert_eq!(packet.position, [1.5, -2.0, 3.25]);
+        assert_eq!(packet.pose, Pose::new(170.0, -20.0, 5.5));
+    }
+
+    #[test]
+    fn rejects_bad_sizes_and_values() {
+        let good = encode([0.0; 6]);
+        assert_eq!(parse(&good[..40]), Err(PacketError::Short(40)));
+        assert_eq!(parse(&[]), Err(PacketError::Short(0)));
+        assert!(matches!(parse(&[good.clone(), vec![0; 8]].concat()), Err(PacketError::Malformed(_))));
+
+        let err = parse(&encode([0.0, 0.0, 0.0, f64::NAN, 0.0, 0.0])).unwrap_err();
+        assert_eq!(err.to_string(), "yaw is NaN");
+        let err = parse(&encode([0.0, 0.0, f64::INFINITY, 0.0, 0.0, 0.0])).unwrap_err();
+        assert_eq!(err.to_string(), "z is inf");
+        let err = parse(&encode([0.0, 0.0, 0.0, 0.0, 0.0, 1e300])).unwrap_err();
+        assert!(err.to_string().starts_with("roll out of range"));
+        assert!(parse(&encode([5000.0, 0.0, 0.0, 0.0, 0.0, 0.0])).is_err());
+    }
+
+    #[test]
+    fn stats_count_each_kind() {
+        let mut stats = PacketStats::default();
+        stats.record_error(PacketError::Short(3));
+        stats.record_error(PacketError::Malformed("pitch is NaN".to_string()));
+        stats.record_error(PacketError::Short(0));
+        assert_eq!((stats.short, stats.malformed), (2, 1));
+        assert_eq!(stats.last_error.as_deref(), Some("short packet (0 of 48 bytes)"));
+    }
+
+    #[test]
+    fn a_queued_batch_keeps_only_the_newest_pose() {
+        let mut stats = PacketStats::default();
+        let mut batch: Vec<_> = [10.0, 20.0, 30.0].iter().map(|&yaw| parse(&encode([0.0, 0.0, 0.0, yaw, 0.0, 0.0]))).collect();
+        batch.push(parse(&[0; 5]));
+        let newest = stats.newest(batch);
+
+        assert_eq!(newest.map(|p| p.pose.yaw), Some(30.0));
+        assert_eq!((stats.received, stats.stale, stats.short), (3, 2, 1));
+        assert_eq!(stats.newest(Vec::new()), None);
+    }
+
+    #[test]
+    fn reader_thread_forwards_every_datagram() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to = socket.local_addr().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        spawn_reader(socket, move |result| tx.send(result).is_ok());
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.send_to(&encode([0.0, 0.0, 0.0, 45.0, 0.0, 0.0]), to).unwrap();
+        sender.send_to(&[0; 60], to).unwrap();
+
+        let wait = std::time::Duration::from_secs(2);
+        assert_eq!(rx.recv_timeout(wait).unwrap().unwrap().pose.yaw, 45.0);
+        assert!(matches!(rx.recv_timeout(wait).unwrap(), Err(PacketError::Malformed(_))));
+    }
+}
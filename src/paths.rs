@@ -0,0 +1,58 @@
+// ==============================================================================
+// USER PATHS
+// ==============================================================================
+//
+// user-writable locations (XDG base dirs), so nothing needs root
+
+use std::path::PathBuf;
+
+fn home() -> PathBuf {
+    std::env::var_os("HOME").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."))
+}
+
+// $XDG_CONFIG_HOME or ~/.config
+pub fn config_home() -> PathBuf {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home().join(".config"),
+    }
+}
+
+// $XDG_DATA_HOME or ~/.local/share
+pub fn data_home() -> PathBuf {
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home().join(".local/share"),
+    }
+}
+
+// $XDG_STATE_HOME or ~/.local/state
+pub fn state_home() -> PathBuf {
+    match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home().join(".local/state"),
+    }
+}
+
+// the filter-chain conf pipewire picks up at startup
+pub fn pipewire_conf() -> PathBuf {
+    config_home().join("pipewire/pipewire.conf.d/99-spatializer.conf")
+}
+
+// where install puts the SOFA and reverb files
+pub fn data_dir() -> PathBuf {
+    data_home().join("spatial-track")
+}
+
+pub fn installed_sofa() -> PathBuf {
+    data_dir().join("subject_021.sofa")
+}
+
+pub fn installed_ir() -> PathBuf {
+    data_dir().join("reverb.wav")
+}
+
+// last radius/width/mode/reverb, written on quit
+pub fn state_file() -> PathBuf {
+    state_home().join("spatial-track/state.toml")
+}
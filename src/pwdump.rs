@@ -0,0 +1,120 @@
+// ==============================================================================
+// PW-DUMP DISCOVERY
+// ==============================================================================
+//
+// finds the filter-chain node in the structured output of 'pw-dump' and
+// matches node.name exactly, so 'effect_input.spatializer2' is not a hit
+// for 'effect_input.spatializer'.
+
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+use crate::backend::{self, NodeInfo};
+
+pub fn discover(node_name: &str) -> Result<Option<NodeInfo>, String> {
+    let output = Command::new("pw-dump")
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run pw-dump: {}", e))?;
+    if !output.status.success() {
+        return Err("pw-dump failed".to_string());
+    }
+    parse_pw_dump(&String::from_utf8_lossy(&output.stdout), node_name)
+}
+
+pub fn parse_pw_dump(json: &str, node_name: &str) -> Result<Option<NodeInfo>, String> {
+    let objects: Value = serde_json::from_str(json).map_err(|e| format!("Invalid pw-dump output: {}", e))?;
+    let objects = objects.as_array().ok_or("pw-dump output is not an array")?;
+
+    let nodes: Vec<&Value> = objects
+        .iter()
+        .filter(|o| o["type"] == "PipeWire:Interface:Node")
+        .collect();
+
+    let Some(node) = nodes.iter().find(|n| node_prop(n, "node.name") == Some(node_name)) else {
+        return Ok(None);
+    };
+
+    let playback = backend::link_group_peer(
+        &node["id"].to_string(),
+        node_prop(node, "node.link-group"),
+        nodes
+            .iter()
+            .map(|n| (n["id"].to_string(), node_prop(n, "node.name").unwrap_or("?"), node_prop(n, "node.link-group"))),
+    );
+
+    Ok(Some(NodeInfo {
+        id: node["id"].to_string(),
+        name: node_name.to_string(),
+        media_class: node_prop(node, "media.class").map(str::to_string),
+        playback,
+        controls: node_controls(node),
+    }))
+}
+
+fn node_prop<'a>(node: &'a Value, key: &str) -> Option<&'a str> {
+    node["info"]["props"][key].as_str()
+}
+
+// control port names, from PropInfo and from the current 'params' in Props
+fn node_controls(node: &Value) -> Vec<String> {
+    let params = &node["info"]["params"];
+    let mut controls: Vec<String> = Vec::new();
+
+    for info in params["PropInfo"].as_array().into_iter().flatten() {
+        if let Some(name) = info["name"].as_str() {
+            controls.push(name.to_string());
+        }
+    }
+
+    for props in params["Props"].as_array().into_iter().flatten() {
+        let pairs = props["params"].as_array().into_iter().flatten();
+        for name in pairs.step_by(2).filter_map(Value::as_str) {
+            controls.push(name.to_string());
+        }
+    }
+
+    controls.sort();
+    controls.dedup();
+    controls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = r#"[
+        { "id": 0, "type": "PipeWire:Interface:Core", "info": {} },
+        { "id": 40, "type": "PipeWire:Interface:Node",
+          "info": { "props": { "node.name": "effect_input.spatializer2", "media.class": "Audio/Sink" } } },
+        { "id": 41, "type": "PipeWire:Interface:Node",
+          "info": {
+            "props": { "node.name": "effect_input.spatializer", "media.class": "Audio/Sink",
+                       "node.link-group": "filter-chain-1-20" },
+            "params": {
+              "PropInfo": [ { "name": "spat_left:Azimuth", "params": true },
+                            { "name": "mix_l:Gain 1", "params": true } ],
+              "Props": [ { "volume": 1.0, "params": [ "spat_left:Azimuth", 30.0, "spat_left:Radius", 1.5 ] } ]
+            } } },
+        { "id": 42, "type": "PipeWire:Interface:Node",
+          "info": { "props": { "node.name": "effect_output.spatializer",
+                               "media.class": "Stream/Output/Audio", "node.link-group": "filter-chain-1-20" } } }
+    ]"#;
+
+    #[test]
+    fn matches_node_name_exactly() {
+        let info = parse_pw_dump(DUMP, "effect_input.spatializer").unwrap().unwrap();
+        assert_eq!(info.id, "41");
+        assert_eq!(info.media_class.as_deref(), Some("Audio/Sink"));
+
+        assert!(parse_pw_dump(DUMP, "effect_input.spatial").unwrap().is_none());
+    }
+
+    #[test]
+    fn finds_playback_node_and_controls() {
+        let info = parse_pw_dump(DUMP, "effect_input.spatializer").unwrap().unwrap();
+        assert_eq!(info.playback, Some(("42".to_string(), "effect_output.spatializer".to_string())));
+        assert_eq!(info.controls, vec!["mix_l:Gain 1", "spat_left:Azimuth", "spat_left:Radius"]);
+    }
+}
@@ -0,0 +1,136 @@
+// ==============================================================================
+// RECENTER
+// ==============================================================================
+//
+// the tracker's zero isn't necessarily where the listener faces. the center is
+// the orientation that counts as straight ahead: captured with C, and with
+// [recenter] auto eased towards the average heading once a new sitting
+// position has been held for a while (yaw only, like the 'follow device' mode
+// of consumer spatial audio). C also makes the current position the middle
+// of the speakers when translation is on.
+
+use crate::config::RecenterConfig;
+use crate::filter::Pose;
+use crate::rotation::{angle_delta, sub, wrap_degrees, Quat, Vec3};
+
+// how quickly the average heading follows the head, seconds
+const AVERAGE_SECS: f64 = 1.0;
+
+#[derive(Default)]
+pub struct Recenter {
+    pub center: Pose,
+    pub origin: Vec3, // listener position that counts as the middle, metres
+    hold: Option<Hold>,
+}
+
+// a sitting position being held
+struct Hold {
+    anchor: f64,  // yaw it started at
+    average: f64, // yaw averaged since
+    held: f64,    // seconds
+}
+
+impl Recenter {
+    // make this orientation straight ahead and this position the middle
+    pub fn capture(&mut self, pose: Pose, position: Vec3) {
+        self.center = pose;
+        self.origin = position;
+        self.hold = None;
+    }
+
+    fn center(&self) -> Quat {
+        Quat::from_yaw_pitch_roll(self.center.yaw, self.center.pitch, self.center.roll)
+    }
+
+    // the head orientation relative to the center
+    pub fn head(&self, pose: Pose) -> Quat {
+        self.center().conjugate().mul(Quat::from_yaw_pitch_roll(pose.yaw, pose.pitch, pose.roll))
+    }
+
+    // the listener's position relative to the origin, turned with the center
+    // so leaning towards straight ahead is always +x
+    pub fn listener(&self, position: Vec3) -> Vec3 {
+        self.center().head_relative(sub(position, self.origin))
+    }
+
+    // auto recenter, once per packet `dt` seconds apart
+    pub fn update(&mut self, pose: Pose, dt: f64, config: &RecenterConfig) {
+        if !config.auto {
+            return;
+        }
+        // moved away from the position: start over from here
+        if self.hold.as_ref().is_some_and(|hold| angle_delta(hold.anchor, pose.yaw).abs() > config.tolerance) {
+            self.hold = None;
+        }
+        let hold = self.hold.get_or_insert(Hold { anchor: pose.yaw, average: pose.yaw, held: 0.0 });
+        hold.held += dt;
+        hold.average = wrap_degrees(hold.average + dt / (AVERAGE_SECS + dt) * angle_delta(hold.average, pose.yaw));
+        if hold.held >= config.hold_secs {
+            let step = config.rate * dt;
+            let remaining = angle_delta(self.center.yaw, hold.average);
+            self.center.yaw = wrap_degrees(self.center.yaw + remaining.clamp(-step, step));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f64 = 0.05;
+
+    fn auto() -> RecenterConfig {
+        RecenterConfig { auto: true, ..RecenterConfig::default() }
+    }
+
+    fn run(recenter: &mut Recenter, yaw: f64, secs: f64, config: &RecenterConfig) {
+        for _ in 0..(secs / DT).round() as usize {
+            recenter.update(Pose::new(yaw, 0.0, 0.0), DT, config);
+        }
+    }
+
+    #[test]
+    fn captured_orientation_is_straight_ahead() {
+        let mut recenter = Recenter::default();
+        let pose = Pose::new(-170.0, 10.0, 5.0);
+        recenter.capture(pose, [0.1, 0.0, 0.0]);
+        assert!(recenter.head(pose).angle_to(Quat::IDENTITY) < 1e-9);
+        assert_eq!(recenter.listener([0.1, 0.0, 0.0]), [0.0, 0.0, 0.0]);
+        // turning 20 degrees right of the center, across the wrap
+        let turned = recenter.head(Pose::new(170.0, 10.0, 5.0));
+        assert!((turned.angle_to(Quat::IDENTITY) - 20.0).abs() < 1e-6);
+
+        // facing 90 left of the tracker, moving along its y axis is moving ahead
+        let mut recenter = Recenter::default();
+        recenter.capture(Pose::new(-90.0, 0.0, 0.0), [0.0; 3]);
+        let ahead = recenter.listener([0.0, 0.2, 0.0]);
+        assert!((ahead[0] - 0.2).abs() < 1e-9 && ahead[1].abs() < 1e-9, "{:?}", ahead);
+    }
+
+    #[test]
+    fn auto_recenter_waits_for_a_held_position_then_eases() {
+        let config = auto();
+        let mut recenter = Recenter::default();
+
+        // not held long enough
+        run(&mut recenter, 30.0, config.hold_secs - 1.0, &config);
+        assert_eq!(recenter.center.yaw, 0.0);
+
+        // held: follows at `rate`, not in one jump, and settles on the heading
+        run(&mut recenter, 30.0, 2.0, &config);
+        assert!(recenter.center.yaw > 0.0 && recenter.center.yaw < config.rate * 1.1, "{}", recenter.center.yaw);
+        run(&mut recenter, 30.0, 30.0, &config);
+        assert!((recenter.center.yaw - 30.0).abs() < 0.01, "{}", recenter.center.yaw);
+
+        // glancing away starts the wait over
+        run(&mut recenter, 90.0, config.hold_secs - 1.0, &config);
+        assert!((recenter.center.yaw - 30.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn auto_recenter_is_off_unless_enabled() {
+        let mut recenter = Recenter::default();
+        run(&mut recenter, 30.0, 60.0, &RecenterConfig::default());
+        assert_eq!(recenter.center, Pose::default());
+    }
+}
@@ -0,0 +1,217 @@
+// ==============================================================================
+// HEAD ROTATION
+// ==============================================================================
+//
+// directions are unit vectors with x ahead, y left and z up; azimuth is + to
+// the left and elevation + up, the same way layouts are written. the head
+// orientation is yaw, then pitch, then roll (intrinsic, like opentrack):
+//   +yaw   = turn right
+//   +pitch = look up
+//   +roll  = tilt the right ear down
+// a speaker's head-relative direction is its world direction rotated by the
+// inverse of that orientation.
+
+pub type Vec3 = [f64; 3];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    // rotation by `degrees` around a unit axis, counterclockwise looking
+    // down the axis
+    pub fn from_axis_angle(axis: Vec3, degrees: f64) -> Self {
+        let (s, c) = (degrees.to_radians() / 2.0).sin_cos();
+        Quat { w: c, x: axis[0] * s, y: axis[1] * s, z: axis[2] * s }
+    }
+
+    // head orientation from opentrack's angles in degrees
+    pub fn from_yaw_pitch_roll(yaw: f64, pitch: f64, roll: f64) -> Self {
+        // turning right is clockwise seen from above, looking up turns the
+        // nose away from the left axis, the right ear goes down around the nose
+        let yaw = Quat::from_axis_angle([0.0, 0.0, 1.0], -yaw);
+        let pitch = Quat::from_axis_angle([0.0, 1.0, 0.0], -pitch);
+        let roll = Quat::from_axis_angle([1.0, 0.0, 0.0], roll);
+        yaw.mul(pitch).mul(roll)
+    }
+
+    // self then other, in the rotated frame (intrinsic)
+    pub fn mul(self, other: Quat) -> Quat {
+        let (a, b) = (self, other);
+        Quat {
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        }
+    }
+
+    // the inverse, for unit quaternions
+    pub fn conjugate(self) -> Quat {
+        Quat { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    pub fn rotate(self, v: Vec3) -> Vec3 {
+        // v' = v + 2w(q x v) + 2q x (q x v)
+        let q = [self.x, self.y, self.z];
+        let t = scale(cross(q, v), 2.0);
+        let u = cross(q, t);
+        [v[0] + self.w * t[0] + u[0], v[1] + self.w * t[1] + u[1], v[2] + self.w * t[2] + u[2]]
+    }
+
+    // size of the turn from one orientation to the other, in degrees
+    pub fn angle_to(self, other: Quat) -> f64 {
+        let dot = self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z;
+        2.0 * dot.abs().min(1.0).acos().to_degrees()
+    }
+
+    // where a world direction is heard with the head in this orientation
+    pub fn head_relative(self, direction: Vec3) -> Vec3 {
+        self.conjugate().rotate(direction)
+    }
+}
+
+pub fn direction(azimuth: f64, elevation: f64) -> Vec3 {
+    let (a, e) = (azimuth.to_radians(), elevation.to_radians());
+    [e.cos() * a.cos(), e.cos() * a.sin(), e.sin()]
+}
+
+// (azimuth, elevation) in degrees; straight up or down reads as azimuth 0
+pub fn to_angles(v: Vec3) -> (f64, f64) {
+    let horizontal = v[0].hypot(v[1]);
+    let azimuth = if horizontal < 1e-12 { 0.0 } else { v[1].atan2(v[0]).to_degrees() };
+    (azimuth, v[2].atan2(horizontal).to_degrees())
+}
+
+// into -180..180
+pub fn wrap_degrees(angle: f64) -> f64 {
+    (angle + 180.0).rem_euclid(360.0) - 180.0
+}
+
+// shortest signed turn from one angle to another, so 179 -> -179 is +2
+pub fn angle_delta(from: f64, to: f64) -> f64 {
+    wrap_degrees(to - from)
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+pub fn scale(v: Vec3, k: f64) -> Vec3 {
+    [v[0] * k, v[1] * k, v[2] * k]
+}
+
+pub fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn length(v: Vec3) -> f64 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heard(azimuth: f64, elevation: f64, yaw: f64, pitch: f64, roll: f64) -> (f64, f64) {
+        let head = Quat::from_yaw_pitch_roll(yaw, pitch, roll);
+        to_angles(head.head_relative(direction(azimuth, elevation)))
+    }
+
+    fn assert_angles(actual: (f64, f64), expected: (f64, f64)) {
+        let close = |a: f64, b: f64| {
+            let diff = (a - b).rem_euclid(360.0);
+            diff.min(360.0 - diff) < 1e-9
+        };
+        assert!(close(actual.0, expected.0) && close(actual.1, expected.1), "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn identity_leaves_every_direction_alone() {
+        for (az, el) in [(0.0, 0.0), (45.0, 0.0), (-135.0, 0.0), (30.0, 45.0), (0.0, -60.0)] {
+            assert_angles(heard(az, el, 0.0, 0.0, 0.0), (az, el));
+        }
+    }
+
+    #[test]
+    fn yaw_turns_everything_the_other_way() {
+        assert_angles(heard(0.0, 0.0, 90.0, 0.0, 0.0), (90.0, 0.0));
+        assert_angles(heard(45.0, 30.0, -30.0, 0.0, 0.0), (15.0, 30.0));
+        // half a turn puts what was behind in front
+        assert_angles(heard(180.0, 0.0, 180.0, 0.0, 0.0), (0.0, 0.0));
+        // +180 and -180 are the same head
+        assert_angles(heard(60.0, 0.0, 180.0, 0.0, 0.0), heard(60.0, 0.0, -180.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn pitch_lowers_the_front_raises_the_back_and_keeps_the_sides() {
+        assert_angles(heard(0.0, 0.0, 0.0, 30.0, 0.0), (0.0, -30.0));
+        assert_angles(heard(180.0, 0.0, 0.0, 30.0, 0.0), (180.0, 30.0));
+        assert_angles(heard(90.0, 0.0, 0.0, 30.0, 0.0), (90.0, 0.0));
+        // looking straight up puts the front source directly below
+        assert_eq!(heard(0.0, 0.0, 0.0, 90.0, 0.0).1.round(), -90.0);
+    }
+
+    #[test]
+    fn roll_moves_the_sides_and_keeps_the_front() {
+        assert_angles(heard(0.0, 0.0, 0.0, 0.0, 40.0), (0.0, 0.0));
+        // right ear down: the left source drops, the right one rises
+        assert_angles(heard(90.0, 0.0, 0.0, 0.0, 30.0), (90.0, -30.0));
+        assert_angles(heard(-90.0, 0.0, 0.0, 0.0, 30.0), (-90.0, 30.0));
+        // the top of the head moves right, so a ceiling speaker ends up
+        // towards the raised left ear
+        assert_angles(heard(0.0, 90.0, 0.0, 0.0, 30.0), (90.0, 60.0));
+    }
+
+    #[test]
+    fn pitch_applies_after_yaw() {
+        // turned right then looking up: the old front source sits at the
+        // left ear, which pitch rotates around, so it stays level
+        assert_angles(heard(0.0, 0.0, 90.0, 30.0, 0.0), (90.0, 0.0));
+        // and what's now in front of the face drops
+        assert_angles(heard(-90.0, 0.0, 90.0, 30.0, 0.0), (0.0, -30.0));
+    }
+
+    #[test]
+    fn full_rotation_keeps_unit_length_and_inverts() {
+        let head = Quat::from_yaw_pitch_roll(123.0, -47.0, 81.0);
+        let v = direction(-20.0, 35.0);
+        let r = head.head_relative(v);
+        assert!(((r[0] * r[0] + r[1] * r[1] + r[2] * r[2]).sqrt() - 1.0).abs() < 1e-12);
+        let back = head.rotate(r);
+        for i in 0..3 {
+            assert!((back[i] - v[i]).abs() < 1e-12);
+        }
+        assert_eq!(Quat::IDENTITY.mul(head), head);
+    }
+
+    #[test]
+    fn angle_to_measures_the_turn_between_orientations() {
+        let a = Quat::from_yaw_pitch_roll(179.0, 0.0, 0.0);
+        let b = Quat::from_yaw_pitch_roll(-179.0, 0.0, 0.0);
+        assert!((a.angle_to(b) - 2.0).abs() < 1e-6);
+        assert!((Quat::IDENTITY.angle_to(Quat::from_yaw_pitch_roll(0.0, 30.0, 0.0)) - 30.0).abs() < 1e-6);
+        assert_eq!(a.angle_to(a), 0.0);
+    }
+
+    #[test]
+    fn angle_delta_takes_the_short_way_round() {
+        assert_eq!(angle_delta(179.0, -179.0), 2.0);
+        assert_eq!(angle_delta(-179.0, 179.0), -2.0);
+        assert_eq!(angle_delta(10.0, 30.0), 20.0);
+        assert_eq!(angle_delta(-170.0, 170.0), -20.0);
+        assert_eq!(wrap_degrees(540.0), -180.0);
+    }
+
+    #[test]
+    fn straight_up_has_a_defined_azimuth() {
+        assert_angles(to_angles([0.0, 0.0, 1.0]), (0.0, 90.0));
+        assert_angles(to_angles([0.0, 0.0, -1.0]), (0.0, -90.0));
+    }
+}
@@ -0,0 +1,161 @@
+// ==============================================================================
+// RUNTIME STATE
+// ==============================================================================
+//
+// the settings changed at runtime (radius, width, layout, reverb, the filter
+// parameters and the recalled preset) are written to
+// ~/.local/state/spatial-track/state.toml on quit and restored on the next
+// start. every key is optional so older state files keep working when new
+// settings are added, and restored values are clamped to the current config.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, Preset};
+use crate::filter::Param;
+use crate::{layout_index, Settings};
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SavedState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    radius: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<f64>,
+    // by name like the preset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    layout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reverb: Option<bool>,
+    // the ema factor, named like the preset key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    smoothing: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deadzone: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_cutoff: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    beta: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    median_window: Option<f64>,
+    // by name, slots move when the config is edited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preset: Option<String>,
+}
+
+// settings from the state file on top of the config defaults; a missing file
+// just means defaults
+pub fn load(path: &Path, config: &Config) -> Result<Settings, String> {
+    let mut settings = Settings::defaults(config);
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(settings),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    let saved: SavedState = toml::from_str(&text).map_err(|e| format!("Invalid state file {}: {}", path.display(), e))?;
+    apply(&mut settings, &saved, config);
+    Ok(settings)
+}
+
+pub fn save(path: &Path, settings: &Settings, config: &Config, presets: &[Preset]) -> Result<(), String> {
+    let saved = SavedState {
+        radius: Some(settings.radius),
+        width: Some(settings.width),
+        layout: config.layouts.get(settings.layout).map(|l| l.name.clone()),
+        reverb: Some(settings.reverb_enabled),
+        smoothing: Some(settings.filter.factor),
+        deadzone: Some(settings.filter.deadzone),
+        min_cutoff: Some(settings.filter.min_cutoff),
+        beta: Some(settings.filter.beta),
+        median_window: Some(settings.filter.median_window as f64),
+        preset: settings.preset.and_then(|slot| presets.get(slot)).map(|p| p.name.clone()),
+    };
+    let text = toml::to_string(&saved).map_err(|e| format!("Failed to encode state: {}", e))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// the config limits may have changed since the state was written
+fn apply(settings: &mut Settings, saved: &SavedState, config: &Config) {
+    if let Some(radius) = saved.radius.filter(|r| r.is_finite()) {
+        settings.radius = radius.clamp(config.radius.min, config.radius.max);
+    }
+    if let Some(width) = saved.width.filter(|w| w.is_finite()) {
+        settings.width = width.clamp(config.width.min, config.width.max);
+    }
+    if let Some(layout) = saved.layout.as_ref().and_then(|name| layout_index(config, name)) {
+        settings.layout = layout;
+    }
+    if let Some(reverb) = saved.reverb {
+        settings.reverb_enabled = reverb;
+    }
+    for (param, value) in [
+        (Param::Factor, saved.smoothing),
+        (Param::Deadzone, saved.deadzone),
+        (Param::MinCutoff, saved.min_cutoff),
+        (Param::Beta, saved.beta),
+        (Param::MedianWindow, saved.median_window),
+    ] {
+        if let Some(value) = value.filter(|v| v.is_finite()) {
+            param.set(&mut settings.filter, value);
+        }
+    }
+    if let Some(name) = &saved.preset {
+        settings.preset = config.presets.iter().position(|p| &p.name == name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_state_round_trips() {
+        let config = Config {
+            presets: vec![Preset { name: "movie".to_string(), ..Default::default() }],
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join(format!("spatial-track-state-{}.toml", std::process::id()));
+        let mut settings = Settings {
+            radius: 3.2,
+            width: 0.7,
+            layout: 1,
+            reverb_enabled: true,
+            preset: Some(0),
+            ..Settings::defaults(&config)
+        };
+        settings.filter.factor = 0.8;
+        settings.filter.beta = 0.3;
+        settings.filter.median_window = 7;
+
+        save(&path, &settings, &config, &config.presets).unwrap();
+        let restored = load(&path, &config).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(restored.radius, 3.2);
+        assert_eq!(restored.width, 0.7);
+        assert_eq!(restored.layout, 1);
+        assert!(restored.reverb_enabled);
+        assert_eq!(restored.filter.factor, 0.8);
+        assert_eq!(restored.filter.beta, 0.3);
+        assert_eq!(restored.filter.median_window, 7);
+        assert_eq!(restored.preset, Some(0));
+    }
+
+    #[test]
+    fn missing_keys_keep_defaults_and_values_are_clamped() {
+        let config = Config::default();
+        let mut settings = Settings::defaults(&config);
+        let saved: SavedState = toml::from_str("radius = 50.0\nlayout = \"back\"\nsome_future_key = 1").unwrap();
+
+        apply(&mut settings, &saved, &config);
+
+        assert_eq!(settings.radius, config.radius.max);
+        assert_eq!(settings.width, config.width.default);
+        assert_eq!(config.layouts[settings.layout].name, "back");
+        assert!(!settings.reverb_enabled);
+    }
+}